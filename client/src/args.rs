@@ -0,0 +1,570 @@
+use crate::*;
+
+contract_method! {
+  RegisterNewAccount => "register_new_account", Call -> () {
+    account_id: AccountId,
+  }
+  optional {
+    referrer_id: AccountId,
+  }
+  deposit(|_args| storage_deposit(ACCOUNT_STORAGE_ESTIMATE))
+}
+
+contract_method! {
+  SendToReceiver => "send_to_receiver", Call -> () {
+    receiver_id: AccountId,
+    y_amount: f64,
+  }
+  deposit(|args| yocto_from_near(args.y_amount))
+}
+
+contract_method! {
+  SendToContract => "send_to_contract", Call -> () {
+    receiver_id: AccountId,
+    y_amount: f64,
+    cash_hold_time: i64, // days
+    campaign_id: String,
+    transaction_id: String,
+  }
+  optional {
+    refund_window: u64, // days
+    schedule: PayoutSchedule,
+  }
+  // Also covers registering the receiver when auto-registration is enabled
+  deposit(|args| {
+    transaction_deposit(yocto_from_near(args.y_amount)) + storage_deposit(ACCOUNT_STORAGE_ESTIMATE)
+  })
+}
+
+contract_method! {
+  CreateCampaign => "create_campaign", Call -> () {
+    campaign_id: String,
+  }
+  optional {
+    refund_window: u64, // days
+    organization_id: String,
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+contract_method! {
+  SetCampaignRefundWindow => "set_campaign_refund_window", Call -> () {
+    campaign_id: String,
+  }
+  optional {
+    refund_window: u64, // days
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+// A `None` policy removes the campaign's policy
+contract_method! {
+  SetCampaignApprovalPolicy => "set_campaign_approval_policy", Call -> () {
+    campaign_id: String,
+    policy: Option<ApprovalPolicyJson>,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  CreateOrganization => "create_organization", Call -> () {
+    organization_id: String,
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+// A `None` role removes the member
+contract_method! {
+  SetOrganizationMember => "set_organization_member", Call -> () {
+    organization_id: String,
+    account_id: AccountId,
+    role: Option<String>,
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+// Only sets the attached deposit, the contract credits whatever is attached
+contract_method! {
+  DepositOrganizationBalance => "deposit_organization_balance", Call -> () {
+    organization_id: String,
+    y_amount: f64,
+  }
+  deposit(|args| yocto_from_near(args.y_amount))
+}
+
+contract_method! {
+  WithdrawOrganizationBalance => "withdraw_organization_balance", Call -> () {
+    organization_id: String,
+    amount: U128,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+// The amount and the transaction's storage are paid from the organization's balance
+contract_method! {
+  SendFromOrganization => "send_from_organization", Call -> () {
+    organization_id: String,
+    receiver_id: AccountId,
+    amount: U128,
+    cash_hold_time: i64, // days
+    campaign_id: String,
+    transaction_id: String,
+  }
+  optional {
+    refund_window: u64, // days
+    schedule: PayoutSchedule,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  ApproveTransaction => "approve_transaction", Call -> () {
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  RejectTransaction => "reject_transaction", Call -> () {
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+// The caller pays for the claim records, whatever they do not use comes back
+contract_method! {
+  ClaimAndWithdraw => "claim_and_withdraw", Call -> () {
+    account_id: AccountId,
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+  gas(|_args| CLAIM_WITH_HOOK_GAS)
+}
+
+contract_method! {
+  SetClaimHookEnabled => "set_claim_hook_enabled", Call -> () {
+    enabled: bool,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  CloseAccount => "close_account", Call -> () {}
+  deposit(|_args| ONE_YOCTO)
+  gas(|_args| ACCOUNT_SWEEP_GAS)
+}
+
+contract_method! {
+  PruneSettledEpochs => "prune_settled_epochs", Call -> () {
+    account_id: AccountId,
+  }
+  gas(|_args| ACCOUNT_SWEEP_GAS)
+}
+
+contract_method! {
+  SetAutoRegisterEnabled => "set_auto_register_enabled", Call -> () {
+    enabled: bool,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetRetentionPeriod => "set_retention_period", Call -> () {
+    retention_period: u64, // days
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetPlatformFee => "set_platform_fee", Call -> () {
+    platform_fee_bps: u16,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetReferralPolicy => "set_referral_policy", Call -> () {
+    referral_level_bps: Vec<u16>,
+    cash_hold_time: u64, // days
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  WithdrawFees => "withdraw_fees", Call -> () {
+    amount: U128,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  ReleaseMilestone => "release_milestone", Call -> () {
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+    milestone_index: u64,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+// Returns whether the oracle released the milestone
+contract_method! {
+  CheckMilestoneOracle => "check_milestone_oracle", Call -> bool {
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+    milestone_index: u64,
+  }
+  gas(|_args| MILESTONE_ORACLE_CHECK_GAS)
+}
+
+contract_method! {
+  ReclaimMilestones => "reclaim_milestones", Call -> U128 {
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+  }
+}
+
+contract_method! {
+  RefundByTransactionId => "refund_by_transaction_id", Call -> () {
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    transaction_id: String,
+    create_epoch: u64,
+  }
+}
+
+contract_method! {
+  RefundByEpoch => "refund_by_epoch", Call -> () {
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    cash_hold_time: u64, // epoch
+  }
+}
+
+// The attached deposit minus storage is credited, attach the amount to prepay on top of
+// `deposit()`
+contract_method! {
+  DepositPrepaidBalance => "deposit_prepaid_balance", Call -> () {}
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+contract_method! {
+  WithdrawPrepaidBalance => "withdraw_prepaid_balance", Call -> () {
+    amount: U128,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  RegisterVoucherKey => "register_voucher_key", Call -> () {
+    public_key: Base58PublicKey,
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+contract_method! {
+  RedeemVoucher => "redeem_voucher", Call -> () {
+    voucher: Voucher,
+    signature: Base64VecU8,
+  }
+}
+
+contract_method! {
+  CreateSubscription => "create_subscription", Call -> u64 {
+    receiver_id: AccountId,
+    amount: U128,
+    period: u64, // days
+    cash_hold_time: u64, // days
+    max_periods: u64,
+    campaign_id: String,
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+contract_method! {
+  ChargeDueSubscriptions => "charge_due_subscriptions", Call -> u64 {
+    from_index: u64,
+    limit: u64,
+  }
+  gas(|args| subscription_charge_gas(args.limit))
+}
+
+contract_method! {
+  PauseSubscription => "pause_subscription", Call -> () {
+    subscription_id: u64,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  ResumeSubscription => "resume_subscription", Call -> () {
+    subscription_id: u64,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  CancelSubscription => "cancel_subscription", Call -> () {
+    subscription_id: u64,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  GetSubscriptions => "get_subscriptions", View -> Vec<SubscriptionJson> {
+    account_id: AccountId,
+  }
+}
+
+// The attached deposit minus storage funds the stream, attach the amount to stream on top of
+// `deposit()`
+contract_method! {
+  CreateStream => "create_stream", Call -> u64 {
+    receiver_id: AccountId,
+    rate_per_second: U128,
+  }
+  optional {
+    start_at: u64, // seconds
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+contract_method! {
+  WithdrawFromStream => "withdraw_from_stream", Call -> U128 {
+    stream_id: u64,
+  }
+}
+
+contract_method! {
+  StopStream => "stop_stream", Call -> U128 {
+    stream_id: u64,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  GetStream => "get_stream", View -> StreamJson {
+    stream_id: u64,
+  }
+}
+
+contract_method! {
+  CreateInvoice => "create_invoice", Call -> String {
+    amount: U128,
+    campaign_id: String,
+    cash_hold_time: i64, // days
+    due_at: u64, // epoch height
+    memo: String,
+  }
+  optional {
+    payer_id: AccountId,
+  }
+  deposit(|_args| storage_deposit(ENTRY_STORAGE_ESTIMATE))
+}
+
+// The invoice amount is not part of the arguments, attach `transaction_deposit(amount)`
+contract_method! {
+  PayInvoice => "pay_invoice", Call -> () {
+    invoice_id: String,
+  }
+  deposit(|_args| storage_deposit(TRANSACTION_STORAGE_ESTIMATE))
+}
+
+contract_method! {
+  CancelInvoice => "cancel_invoice", Call -> () {
+    invoice_id: String,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  GetInvoice => "get_invoice", View -> InvoiceJson {
+    invoice_id: String,
+  }
+}
+
+contract_method! {
+  SetBlockedSender => "set_blocked_sender", Call -> () {
+    account_id: AccountId,
+    blocked: bool,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetBlockedReceiver => "set_blocked_receiver", Call -> () {
+    account_id: AccountId,
+    blocked: bool,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetAllowlistEnabled => "set_allowlist_enabled", Call -> () {
+    enabled: bool,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetAllowedAccount => "set_allowed_account", Call -> () {
+    account_id: AccountId,
+    allowed: bool,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetAttester => "set_attester", Call -> () {
+    account_id: AccountId,
+    enabled: bool,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  SetTierLimit => "set_tier_limit", Call -> () {
+    kyc_tier: u8,
+    limit: TierLimitJson,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  AttestKycTier => "attest_kyc_tier", Call -> () {
+    account_id: AccountId,
+    kyc_tier: u8,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+// A `None` limit removes the sender's limit
+contract_method! {
+  SetSenderVelocityLimit => "set_sender_velocity_limit", Call -> () {
+    sender_id: AccountId,
+    limit: Option<VelocityLimitJson>,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+// A `None` limit removes the campaign's limit
+contract_method! {
+  SetCampaignVelocityLimit => "set_campaign_velocity_limit", Call -> () {
+    campaign_id: String,
+    limit: Option<VelocityLimitJson>,
+  }
+  deposit(|_args| ONE_YOCTO)
+}
+
+contract_method! {
+  GetSenderVelocityLimit => "get_sender_velocity_limit", View -> Option<VelocityLimitJson> {
+    sender_id: AccountId,
+  }
+}
+
+contract_method! {
+  GetCampaignVelocityLimit => "get_campaign_velocity_limit", View -> Option<VelocityLimitJson> {
+    campaign_id: String,
+  }
+}
+
+contract_method! {
+  GetTierLimit => "get_tier_limit", View -> Option<TierLimitJson> {
+    kyc_tier: u8,
+  }
+}
+
+contract_method! {
+  GetAccountScreening => "get_account_screening", View -> AccountScreeningJson {
+    account_id: AccountId,
+  }
+}
+
+contract_method! {
+  GetCampaignInfo => "get_campaign_info", View -> CampaignJson {
+    campaign_id: String,
+  }
+}
+
+contract_method! {
+  GetCampaignStats => "get_campaign_stats", View -> CampaignStatsJson {
+    campaign_id: String,
+  }
+}
+
+contract_method! {
+  GetTopReceivers => "get_top_receivers", View -> Vec<CampaignReceiverJson> {
+    campaign_id: String,
+    from_index: u64,
+    limit: u64,
+  }
+}
+
+contract_method! {
+  GetDashboard => "get_dashboard", View -> DashboardJson {
+    recent_epochs: u64, // at most DASHBOARD_EPOCHS
+  }
+}
+
+contract_method! {
+  GetAccounts => "get_accounts", View -> Vec<AccountJson> {
+    from_index: u64,
+    limit: u64,
+  }
+}
+
+contract_method! {
+  GetOrganization => "get_organization", View -> OrganizationJson {
+    organization_id: String,
+  }
+}
+
+contract_method! {
+  GetFeePolicy => "get_fee_policy", View -> FeePolicyJson {}
+}
+
+contract_method! {
+  GetReferralTree => "get_referral_tree", View -> ReferralNodeJson {
+    account_id: AccountId,
+    depth: u64, // at most MAX_REFERRAL_LEVELS
+  }
+}
+
+contract_method! {
+  GetPrepaidBalance => "get_prepaid_balance", View -> U128 {
+    account_id: AccountId,
+  }
+}
+
+contract_method! {
+  GetReconciliation => "get_reconciliation", View -> ReconciliationJson {}
+}
+
+contract_method! {
+  GetAccountInfo => "get_account_info", View -> AccountJson {
+    account_id: AccountId,
+  }
+}
+
+contract_method! {
+  GetTransactionsInfo => "get_transactions_info", View -> Vec<TransferTransactionJson> {
+    account_id: AccountId,
+    start_epoch: u64,
+    end_epoch: u64,
+  }
+}
+
+contract_method! {
+  GetStatement => "get_statement", View -> StatementJson {
+    account_id: AccountId,
+    from_epoch: u64,
+    to_epoch: u64,
+    start_epoch: u64,
+    limit: u64,
+  }
+}
@@ -0,0 +1,47 @@
+use crate::*;
+
+pub const ONE_YOCTO: Balance = 1;
+pub const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+// Protocol storage price, 1 NEAR per 100kb
+pub const STORAGE_BYTE_COST: Balance = 10_000_000_000_000_000_000;
+
+// Upper bounds of the storage a new account or a new campaign, subscription, stream,
+// invoice or voucher key can use, in bytes
+pub const ACCOUNT_STORAGE_ESTIMATE: u64 = 500;
+pub const ENTRY_STORAGE_ESTIMATE: u64 = 500;
+
+pub const TGAS: Gas = 1_000_000_000_000;
+pub const DEFAULT_CALL_GAS: Gas = 30 * TGAS;
+pub const MAX_GAS: Gas = 300 * TGAS;
+// Each charged subscription creates a transaction and may pay back storage
+pub const SUBSCRIPTION_CHARGE_GAS: Gas = 10 * TGAS;
+// Closing and pruning walk every epoch bucket of the account
+pub const ACCOUNT_SWEEP_GAS: Gas = 100 * TGAS;
+// The oracle call and the callback on top of the call itself
+pub const MILESTONE_ORACLE_CHECK_GAS: Gas =
+  DEFAULT_CALL_GAS + MILESTONE_ORACLE_GAS + MILESTONE_CALLBACK_GAS;
+// Covers the receiver's hook and the payout callback, the hook's gas is unused when it is disabled
+pub const CLAIM_WITH_HOOK_GAS: Gas = DEFAULT_CALL_GAS + CLAIM_HOOK_GAS + CLAIM_CALLBACK_GAS;
+
+// Same conversion as the contract applies to `y_amount`
+pub fn yocto_from_near(y_amount: f64) -> Balance {
+  (y_amount * 1_000_000_000_000_000_000_000_000_f64) as u128
+}
+
+pub fn storage_deposit(bytes: u64) -> Balance {
+  STORAGE_BYTE_COST * Balance::from(bytes)
+}
+
+// Covers the highest platform fee the owner can set, the contract refunds what is left over
+pub fn transaction_deposit(amount: Balance) -> Balance {
+  amount
+    + amount * MAX_PLATFORM_FEE_BPS as u128 / 10_000
+    + storage_deposit(TRANSACTION_STORAGE_ESTIMATE)
+}
+
+pub fn subscription_charge_gas(limit: u64) -> Gas {
+  std::cmp::min(
+    DEFAULT_CALL_GAS + SUBSCRIPTION_CHARGE_GAS.saturating_mul(limit),
+    MAX_GAS,
+  )
+}
@@ -0,0 +1,207 @@
+// Typed arguments, return values and events of the hurdle-payment contract's JSON interface.
+// The request and response types are the contract's own, so the two cannot drift apart.
+pub use hurdle_payment::{
+  voucher_message, AccountEventData, AccountJson, AccountScreeningJson, ApprovalPolicyJson,
+  ApprovalRecordedData, CampaignCreatedData, CampaignJson, CampaignReceiverJson, CampaignStatsJson,
+  ClaimPayoutFailedData, ClaimedPayment, DashboardJson, EventLog, FeePolicyJson, HurdleEvent,
+  InvoiceJson, Milestone, MilestoneData, OrganizationJson, OrganizationMemberJson, PayoutSchedule,
+  ReconciliationJson, ReferralNodeJson, StatementJson, StatementLineJson, StreamJson,
+  StreamWithdrawnData, SubscriptionJson, TierLimitJson, Tranche, TransactionApproval,
+  TransactionClaimedData, TransactionRefundedData, TransferTransactionJson,
+  VelocityLimitExceededData, VelocityLimitJson, Voucher, CLAIM_CALLBACK_GAS, CLAIM_HOOK_GAS,
+  DASHBOARD_EPOCHS, EVENT_STANDARD, EVENT_VERSION, MAX_PLATFORM_FEE_BPS, MAX_REFERRAL_LEVELS,
+  MILESTONE_CALLBACK_GAS, MILESTONE_ORACLE_GAS, ORGANIZATION_ROLES, TRANSACTION_STORAGE_ESTIMATE,
+  VOUCHER_DOMAIN,
+};
+use near_sdk::json_types::{Base58PublicKey, Base64VecU8, U128};
+use near_sdk::serde::de::DeserializeOwned;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json;
+use near_sdk::{AccountId, Balance, Gas};
+
+#[macro_use]
+mod method;
+pub use crate::estimate::*;
+pub use crate::method::*;
+mod estimate;
+pub use crate::args::*;
+mod args;
+pub use crate::statement::*;
+mod statement;
+
+// Collects the contract's events from the logs of a transaction outcome, other logs are skipped
+pub fn parse_event_logs<S: AsRef<str>>(logs: &[S]) -> Vec<EventLog> {
+  logs
+    .iter()
+    .filter_map(|log| EventLog::parse(log.as_ref()))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use hurdle_payment::HurdlePayment;
+  use near_sdk::json_types::ValidAccountId;
+  use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+  use near_sdk::{env, testing_env, MockedBlockchain};
+
+  fn get_context(predecessor_id: ValidAccountId, attached_deposit: Balance) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+      .current_account_id(accounts(0))
+      .signer_account_id(predecessor_id.clone())
+      .predecessor_account_id(predecessor_id)
+      .attached_deposit(attached_deposit)
+      .account_balance(100 * ONE_NEAR);
+    builder
+  }
+
+  #[test]
+  fn test_args_json() {
+    let args = SendToContract::new(
+      accounts(1).to_string(),
+      1.5,
+      3,
+      "campaign".to_string(),
+      "tx1".to_string(),
+    )
+    .refund_window(1);
+    let json: serde_json::Value = serde_json::from_slice(&args.args_json()).unwrap();
+    assert_eq!(SendToContract::METHOD_NAME, "send_to_contract");
+    assert_eq!(json["receiver_id"], "bob");
+    assert_eq!(json["refund_window"], 1);
+    assert!(json["schedule"].is_null());
+    assert_eq!(
+      args.deposit(),
+      yocto_from_near(1.5)
+        + yocto_from_near(1.5) / 10
+        + storage_deposit(TRANSACTION_STORAGE_ESTIMATE + ACCOUNT_STORAGE_ESTIMATE)
+    );
+    assert!(ClaimAndWithdraw::parse_output(b"").is_ok());
+    assert_eq!(GetPrepaidBalance::parse_output(b"\"42\"").unwrap().0, 42);
+  }
+
+  #[test]
+  fn test_statement_export() {
+    let statement = StatementJson {
+      account_id: "bob".to_string(),
+      from_epoch: 10,
+      to_epoch: 20,
+      opening_locked_balance: U128(ONE_NEAR),
+      new_locks: U128(ONE_NEAR / 2),
+      claims: U128(ONE_NEAR),
+      refunds: U128(0),
+      fees: U128(0),
+      closing_locked_balance: U128(ONE_NEAR / 2),
+      line_items: vec![StatementLineJson {
+        epoch: 12,
+        kind: "LOCK".to_string(),
+        transaction_id: "order, 7".to_string(),
+        sender_id: "alice".to_string(),
+        campaign_id: "spring".to_string(),
+        amount: U128(ONE_NEAR / 2),
+      }],
+      next_epoch: None,
+    };
+    assert_eq!(format_near(ONE_NEAR / 2), "0.5");
+    assert_eq!(format_near(3 * ONE_NEAR), "3");
+    assert_eq!(format_near(1), "0.000000000000000000000001");
+
+    let csv = statement_csv(&statement);
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[1], "10,OPENING,,,,1,1000000000000000000000000");
+    assert_eq!(
+      rows[2],
+      "12,LOCK,\"order, 7\",alice,spring,0.5,500000000000000000000000"
+    );
+    assert!(rows[3].starts_with("20,CLOSING,"));
+
+    let json: serde_json::Value = serde_json::from_str(&statement_json(&statement)).unwrap();
+    assert_eq!(json["claims"]["near"], "1");
+    assert_eq!(
+      json["line_items"][0]["amount"]["yocto"],
+      "500000000000000000000000"
+    );
+  }
+
+  #[test]
+  fn test_estimated_deposits_are_accepted() {
+    testing_env!(get_context(accounts(0), 0).build());
+    let mut contract = HurdlePayment::new();
+
+    let register = RegisterNewAccount::new(accounts(1).to_string());
+    testing_env!(get_context(accounts(0), register.deposit())
+      .storage_usage(env::storage_usage())
+      .build());
+    contract.register_new_account(register.account_id, register.referrer_id);
+
+    let send = SendToContract::new(
+      accounts(1).to_string(),
+      1.0,
+      1,
+      "campaign".to_string(),
+      "tx1".to_string(),
+    );
+    testing_env!(get_context(accounts(2), send.deposit())
+      .storage_usage(env::storage_usage())
+      .build());
+    contract.send_to_contract(
+      send.receiver_id,
+      send.y_amount,
+      send.cash_hold_time,
+      send.campaign_id,
+      send.transaction_id,
+      send.refund_window,
+      send.schedule,
+    );
+
+    // Responses decode into the same types the contract serialized
+    let info = serde_json::to_vec(&contract.get_account_info(accounts(1).to_string())).unwrap();
+    let info = GetAccountInfo::parse_output(&info).unwrap();
+    assert_eq!(info.locked_balance.0, yocto_from_near(1.0));
+  }
+
+  #[test]
+  fn test_parse_event_logs() {
+    testing_env!(get_context(accounts(0), 0).build());
+    let mut contract = HurdlePayment::new();
+    testing_env!(get_context(accounts(0), ONE_YOCTO)
+      .storage_usage(env::storage_usage())
+      .build());
+    contract.set_sender_velocity_limit(
+      accounts(2).to_string(),
+      Some(VelocityLimitJson {
+        window: 60,
+        max_amount: None,
+        max_count: Some(0),
+      }),
+    );
+
+    testing_env!(get_context(accounts(2), ONE_NEAR)
+      .storage_usage(env::storage_usage())
+      .build());
+    contract.send_to_contract(
+      accounts(1).to_string(),
+      0.5,
+      1,
+      "campaign".to_string(),
+      "tx1".to_string(),
+      None,
+      None,
+    );
+    let mut logs = get_logs();
+    logs.push("not an event".to_string());
+    let events = parse_event_logs(&logs);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].standard, EVENT_STANDARD);
+    match &events[0].event {
+      HurdleEvent::VelocityLimitExceeded(data) => {
+        assert_eq!(data[0].scope, "sender");
+        assert_eq!(data[0].limit, "count");
+        assert_eq!(data[0].amount.0, yocto_from_near(0.5));
+      }
+      _ => panic!("Expected a velocity_limit_exceeded event"),
+    }
+  }
+}
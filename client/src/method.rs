@@ -0,0 +1,94 @@
+use crate::*;
+
+pub enum MethodKind {
+  View,
+  Call,
+}
+
+// A contract method together with its typed arguments and return value
+pub trait ContractMethod: Serialize {
+  const METHOD_NAME: &'static str;
+  const KIND: MethodKind;
+  type Output: DeserializeOwned;
+
+  fn args_json(&self) -> Vec<u8> {
+    serde_json::to_vec(self).unwrap()
+  }
+
+  // Deposit to attach, storage is estimated from above and the contract refunds the excess
+  fn deposit(&self) -> Balance {
+    0
+  }
+
+  fn gas(&self) -> Gas {
+    match Self::KIND {
+      MethodKind::View => 0,
+      MethodKind::Call => DEFAULT_CALL_GAS,
+    }
+  }
+
+  // Methods without a return value produce an empty result
+  fn parse_output(result: &[u8]) -> serde_json::Result<Self::Output> {
+    if result.is_empty() {
+      return serde_json::from_slice(b"null");
+    }
+    serde_json::from_slice(result)
+  }
+}
+
+// Declares the argument struct of a method, a constructor taking the required arguments
+// and a setter for every optional one
+macro_rules! contract_method {
+  (
+    $name:ident => $method:literal, $kind:ident -> $output:ty {
+      $($field:ident: $ty:ty),* $(,)?
+    }
+    $(optional { $($optional:ident: $optional_ty:ty),* $(,)? })?
+    $(deposit(|$deposit_args:ident| $deposit:expr))?
+    $(gas(|$gas_args:ident| $gas:expr))?
+  ) => {
+    #[derive(Deserialize, Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    pub struct $name {
+      $(pub $field: $ty,)*
+      $($(pub $optional: Option<$optional_ty>,)*)?
+    }
+
+    impl $name {
+      #[allow(clippy::new_without_default, clippy::too_many_arguments)]
+      pub fn new($($field: $ty),*) -> Self {
+        $name {
+          $($field,)*
+          $($($optional: None,)*)?
+        }
+      }
+
+      $($(
+        pub fn $optional(mut self, $optional: $optional_ty) -> Self {
+          self.$optional = Some($optional);
+          self
+        }
+      )*)?
+    }
+
+    impl ContractMethod for $name {
+      const METHOD_NAME: &'static str = $method;
+      const KIND: MethodKind = MethodKind::$kind;
+      type Output = $output;
+
+      $(
+        fn deposit(&self) -> Balance {
+          let $deposit_args = self;
+          $deposit
+        }
+      )?
+
+      $(
+        fn gas(&self) -> Gas {
+          let $gas_args = self;
+          $gas
+        }
+      )?
+    }
+  };
+}
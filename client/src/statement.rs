@@ -0,0 +1,113 @@
+use crate::*;
+
+const YOCTO_DIGITS: usize = 24;
+
+// Exact decimal NEAR amount, "1.5" for 1.5 * 10^24 yoctoNEAR
+pub fn format_near(amount: Balance) -> String {
+  let whole = amount / ONE_NEAR;
+  let fraction = format!("{:0width$}", amount % ONE_NEAR, width = YOCTO_DIGITS);
+  let fraction = fraction.trim_end_matches('0');
+  if fraction.is_empty() {
+    whole.to_string()
+  } else {
+    format!("{}.{}", whole, fraction)
+  }
+}
+
+// Shared with the state inspector's exports so every CSV quotes the same way
+pub fn csv_field(field: &str) -> String {
+  if field.contains([',', '"', '\n']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
+
+pub fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+  let fields: Vec<String> = fields
+    .iter()
+    .map(|field| csv_field(field.as_ref()))
+    .collect();
+  format!("{}\n", fields.join(","))
+}
+
+// One row per line item between an OPENING and a CLOSING balance row, amounts in NEAR and yocto
+pub fn statement_csv(statement: &StatementJson) -> String {
+  let mut csv = csv_row(&[
+    "epoch",
+    "kind",
+    "transaction_id",
+    "sender_id",
+    "campaign_id",
+    "amount_near",
+    "amount_yocto",
+  ]);
+  let balance_row = |epoch: u64, kind: &str, amount: Balance| {
+    csv_row(&[
+      &epoch.to_string(),
+      kind,
+      "",
+      "",
+      "",
+      &format_near(amount),
+      &amount.to_string(),
+    ])
+  };
+  csv.push_str(&balance_row(
+    statement.from_epoch,
+    "OPENING",
+    statement.opening_locked_balance.0,
+  ));
+  for line_item in statement.line_items.iter() {
+    csv.push_str(&csv_row(&[
+      &line_item.epoch.to_string(),
+      &line_item.kind,
+      &line_item.transaction_id,
+      &line_item.sender_id,
+      &line_item.campaign_id,
+      &format_near(line_item.amount.0),
+      &line_item.amount.0.to_string(),
+    ]));
+  }
+  csv.push_str(&balance_row(
+    statement.to_epoch,
+    "CLOSING",
+    statement.closing_locked_balance.0,
+  ));
+  csv
+}
+
+// The statement with every amount as a decimal NEAR string next to its yocto value
+pub fn statement_json(statement: &StatementJson) -> String {
+  let amount =
+    |amount: &U128| serde_json::json!({ "near": format_near(amount.0), "yocto": amount });
+  let line_items: Vec<serde_json::Value> = statement
+    .line_items
+    .iter()
+    .map(|line_item| {
+      serde_json::json!({
+        "epoch": line_item.epoch,
+        "kind": line_item.kind,
+        "transaction_id": line_item.transaction_id,
+        "sender_id": line_item.sender_id,
+        "campaign_id": line_item.campaign_id,
+        "amount": amount(&line_item.amount),
+      })
+    })
+    .collect();
+  let report = serde_json::json!({
+    "account_id": statement.account_id,
+    "from_epoch": statement.from_epoch,
+    "to_epoch": statement.to_epoch,
+    "currency": "NEAR",
+    "opening_locked_balance": amount(&statement.opening_locked_balance),
+    "new_locks": amount(&statement.new_locks),
+    "claims": amount(&statement.claims),
+    "refunds": amount(&statement.refunds),
+    "fees": amount(&statement.fees),
+    "closing_locked_balance": amount(&statement.closing_locked_balance),
+    "line_item_count": statement.line_items.len(),
+    "line_items": line_items,
+  });
+  serde_json::to_string_pretty(&report).unwrap()
+}
@@ -0,0 +1,487 @@
+use crate::*;
+use hurdle_payment::{
+  AccountEventData, CampaignCreatedData, EventLog, HurdleEvent, MilestoneData, StreamWithdrawnData,
+  TransactionClaimedData, TransactionRefundedData, TransferTransactionJson,
+};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+  receipt_id TEXT NOT NULL,
+  log_index INTEGER NOT NULL,
+  block_height INTEGER NOT NULL,
+  event TEXT NOT NULL,
+  data TEXT NOT NULL,
+  PRIMARY KEY (receipt_id, log_index)
+);
+CREATE TABLE IF NOT EXISTS accounts (
+  account_id TEXT PRIMARY KEY,
+  locked_balance TEXT NOT NULL,
+  total_revenue TEXT NOT NULL,
+  registered_at INTEGER NOT NULL,
+  closed_at INTEGER
+);
+CREATE TABLE IF NOT EXISTS campaigns (
+  campaign_id TEXT PRIMARY KEY,
+  owner_id TEXT NOT NULL,
+  refund_window INTEGER,
+  created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+  receiver_id TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  transaction_id TEXT NOT NULL,
+  sender_id TEXT NOT NULL,
+  campaign_id TEXT NOT NULL,
+  locked_balance TEXT NOT NULL,
+  claimed_amount TEXT NOT NULL,
+  claimable_at INTEGER NOT NULL,
+  refundable_until INTEGER NOT NULL,
+  status TEXT NOT NULL,
+  PRIMARY KEY (receiver_id, created_at, transaction_id)
+);
+CREATE TABLE IF NOT EXISTS status_changes (
+  receipt_id TEXT NOT NULL,
+  log_index INTEGER NOT NULL,
+  block_height INTEGER NOT NULL,
+  receiver_id TEXT NOT NULL,
+  created_at INTEGER NOT NULL,
+  transaction_id TEXT NOT NULL,
+  from_status TEXT,
+  to_status TEXT NOT NULL,
+  amount TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS status_changes_by_transaction
+  ON status_changes (receiver_id, created_at, transaction_id);
+";
+
+// One line of the input, the logs of a single receipt outcome
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReceiptLogs {
+  pub receipt_id: String,
+  pub block_height: u64,
+  pub logs: Vec<String>,
+  // Execution status as returned by the RPC, logs of failed receipts did not change any state
+  #[serde(default)]
+  pub status: Option<serde_json::Value>,
+}
+
+impl ReceiptLogs {
+  fn failed(&self) -> bool {
+    self
+      .status
+      .as_ref()
+      .is_some_and(|status| status.get("Failure").is_some())
+  }
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct IngestStats {
+  pub receipts: u64,
+  pub events: u64,
+  pub duplicates: u64,
+  pub failed_receipts: u64,
+}
+
+// Where an event comes from, recorded with every status change
+struct EventSource<'a> {
+  receipt_id: &'a str,
+  log_index: usize,
+  block_height: u64,
+}
+
+// Balances are kept as decimal strings, they do not fit in an SQLite integer
+fn parse_balance(value: String) -> Balance {
+  value.parse().unwrap()
+}
+
+pub struct Ledger {
+  connection: Connection,
+}
+
+impl Ledger {
+  pub fn open(path: &str) -> rusqlite::Result<Self> {
+    let connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    Ok(Ledger { connection })
+  }
+
+  pub fn connection(&self) -> &Connection {
+    &self.connection
+  }
+
+  // Applies every event not seen before, replaying the same file twice changes nothing
+  pub fn ingest<R: BufRead>(&mut self, input: R) -> Result<IngestStats, String> {
+    let mut stats = IngestStats::default();
+    let db = self
+      .connection
+      .transaction()
+      .map_err(|error| error.to_string())?;
+    for (line_number, line) in input.lines().enumerate() {
+      let line = line.map_err(|error| error.to_string())?;
+      if line.trim().is_empty() {
+        continue;
+      }
+      let receipt: ReceiptLogs = serde_json::from_str(&line)
+        .map_err(|error| format!("Line {}: {}", line_number + 1, error))?;
+      stats.receipts += 1;
+      if receipt.failed() {
+        stats.failed_receipts += 1;
+        continue;
+      }
+      for (log_index, log) in receipt.logs.iter().enumerate() {
+        let event_log = match EventLog::parse(log) {
+          Some(event_log) => event_log,
+          None => continue,
+        };
+        let source = EventSource {
+          receipt_id: &receipt.receipt_id,
+          log_index,
+          block_height: receipt.block_height,
+        };
+        let applied =
+          apply_event(&db, &source, &event_log.event).map_err(|error| error.to_string())?;
+        if applied {
+          stats.events += 1;
+        } else {
+          stats.duplicates += 1;
+        }
+      }
+    }
+    db.commit().map_err(|error| error.to_string())?;
+    Ok(stats)
+  }
+
+  pub fn account_balances(&self, account_id: &str) -> rusqlite::Result<Option<(Balance, Balance)>> {
+    self
+      .connection
+      .query_row(
+        "SELECT locked_balance, total_revenue FROM accounts
+         WHERE account_id = ?1 AND closed_at IS NULL",
+        params![account_id],
+        |row| Ok((parse_balance(row.get(0)?), parse_balance(row.get(1)?))),
+      )
+      .optional()
+  }
+}
+
+fn apply_event(
+  db: &Transaction,
+  source: &EventSource,
+  event: &HurdleEvent,
+) -> rusqlite::Result<bool> {
+  let (name, data) = match serde_json::to_value(event).unwrap() {
+    serde_json::Value::Object(mut fields) => (
+      fields["event"].as_str().unwrap().to_string(),
+      fields.remove("data").unwrap().to_string(),
+    ),
+    _ => unreachable!(),
+  };
+  let inserted = db.execute(
+    "INSERT OR IGNORE INTO events (receipt_id, log_index, block_height, event, data)
+     VALUES (?1, ?2, ?3, ?4, ?5)",
+    params![
+      source.receipt_id,
+      source.log_index,
+      source.block_height,
+      name,
+      data
+    ],
+  )?;
+  if inserted == 0 {
+    return Ok(false);
+  }
+
+  match event {
+    HurdleEvent::AccountRegistered(accounts) => {
+      for AccountEventData { account_id } in accounts {
+        // A closed account can be registered again and starts over
+        db.execute(
+          "INSERT INTO accounts (account_id, locked_balance, total_revenue, registered_at)
+           VALUES (?1, '0', '0', ?2)
+           ON CONFLICT (account_id) DO UPDATE SET
+             locked_balance = '0', total_revenue = '0', registered_at = ?2, closed_at = NULL",
+          params![account_id, source.block_height],
+        )?;
+      }
+    }
+    HurdleEvent::AccountClosed(accounts) => {
+      for AccountEventData { account_id } in accounts {
+        db.execute(
+          "UPDATE accounts SET closed_at = ?2 WHERE account_id = ?1",
+          params![account_id, source.block_height],
+        )?;
+      }
+    }
+    HurdleEvent::CampaignCreated(campaigns) => {
+      for CampaignCreatedData {
+        campaign_id,
+        owner_id,
+        refund_window,
+      } in campaigns
+      {
+        db.execute(
+          "INSERT OR REPLACE INTO campaigns (campaign_id, owner_id, refund_window, created_at)
+           VALUES (?1, ?2, ?3, ?4)",
+          params![campaign_id, owner_id, refund_window, source.block_height],
+        )?;
+      }
+    }
+    HurdleEvent::TransactionLocked(transactions) => {
+      for transaction in transactions {
+        apply_locked(db, source, transaction)?;
+      }
+    }
+    HurdleEvent::TransactionClaimed(claims) => {
+      for claim in claims {
+        apply_claimed(db, source, claim)?;
+      }
+    }
+    HurdleEvent::TransactionRefunded(refunds) => {
+      for refund in refunds {
+        apply_refunded(db, source, refund)?;
+      }
+    }
+    HurdleEvent::MilestoneReclaimed(reclaims) => {
+      for reclaim in reclaims {
+        apply_reclaimed(db, source, reclaim)?;
+      }
+    }
+    // Streams are paid out directly, only the receiver's revenue changes
+    HurdleEvent::StreamWithdrawn(withdrawals) => {
+      for StreamWithdrawnData {
+        receiver_id,
+        amount,
+        ..
+      } in withdrawals
+      {
+        update_account(db, receiver_id, 0, amount.0 as i128)?;
+      }
+    }
+    // Releases and approvals move no funds, the claim or refund that follows does. A failed
+    // payout relocks payments whose claim was never reported
+    HurdleEvent::VelocityLimitExceeded(_)
+    | HurdleEvent::MilestoneReleased(_)
+    | HurdleEvent::ApprovalRecorded(_)
+    | HurdleEvent::ClaimPayoutFailed(_) => {}
+  }
+  Ok(true)
+}
+
+fn update_account(
+  db: &Transaction,
+  account_id: &str,
+  locked_delta: i128,
+  revenue_delta: i128,
+) -> rusqlite::Result<()> {
+  let balances: Option<(String, String)> = db
+    .query_row(
+      "SELECT locked_balance, total_revenue FROM accounts WHERE account_id = ?1",
+      params![account_id],
+      |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?;
+  let (locked_balance, total_revenue) = match balances {
+    Some((locked_balance, total_revenue)) => {
+      (parse_balance(locked_balance), parse_balance(total_revenue))
+    }
+    None => return Ok(()),
+  };
+  db.execute(
+    "UPDATE accounts SET locked_balance = ?2, total_revenue = ?3 WHERE account_id = ?1",
+    params![
+      account_id,
+      locked_balance
+        .saturating_add_signed(locked_delta)
+        .to_string(),
+      total_revenue
+        .saturating_add_signed(revenue_delta)
+        .to_string()
+    ],
+  )?;
+  Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_status_change(
+  db: &Transaction,
+  source: &EventSource,
+  receiver_id: &str,
+  created_at: u64,
+  transaction_id: &str,
+  from_status: Option<&str>,
+  to_status: &str,
+  amount: Balance,
+) -> rusqlite::Result<()> {
+  db.execute(
+    "INSERT INTO status_changes (receipt_id, log_index, block_height, receiver_id, created_at,
+       transaction_id, from_status, to_status, amount)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    params![
+      source.receipt_id,
+      source.log_index,
+      source.block_height,
+      receiver_id,
+      created_at,
+      transaction_id,
+      from_status,
+      to_status,
+      amount.to_string()
+    ],
+  )?;
+  Ok(())
+}
+
+fn transaction_status(
+  db: &Transaction,
+  receiver_id: &str,
+  created_at: u64,
+  transaction_id: &str,
+) -> rusqlite::Result<Option<(String, Balance)>> {
+  db.query_row(
+    "SELECT status, claimed_amount FROM transactions
+     WHERE receiver_id = ?1 AND created_at = ?2 AND transaction_id = ?3",
+    params![receiver_id, created_at, transaction_id],
+    |row| Ok((row.get(0)?, parse_balance(row.get(1)?))),
+  )
+  .optional()
+}
+
+fn apply_locked(
+  db: &Transaction,
+  source: &EventSource,
+  transaction: &TransferTransactionJson,
+) -> rusqlite::Result<()> {
+  db.execute(
+    "INSERT OR REPLACE INTO transactions (receiver_id, created_at, transaction_id, sender_id,
+       campaign_id, locked_balance, claimed_amount, claimable_at, refundable_until, status)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    params![
+      transaction.receiver_id,
+      transaction.created_at,
+      transaction.transaction_id,
+      transaction.sender_id,
+      transaction.campaign_id,
+      transaction.locked_balance.0.to_string(),
+      transaction.claimed_amount.0.to_string(),
+      transaction.claimable_at,
+      transaction.refundable_until,
+      transaction.status
+    ],
+  )?;
+  let amount = transaction.locked_balance.0;
+  update_account(db, &transaction.receiver_id, amount as i128, amount as i128)?;
+  record_status_change(
+    db,
+    source,
+    &transaction.receiver_id,
+    transaction.created_at,
+    &transaction.transaction_id,
+    None,
+    &transaction.status,
+    amount,
+  )
+}
+
+fn apply_claimed(
+  db: &Transaction,
+  source: &EventSource,
+  claim: &TransactionClaimedData,
+) -> rusqlite::Result<()> {
+  let previous = transaction_status(
+    db,
+    &claim.receiver_id,
+    claim.created_at,
+    &claim.transaction_id,
+  )?;
+  if let Some((_, claimed_amount)) = &previous {
+    db.execute(
+      "UPDATE transactions SET claimed_amount = ?4, status = ?5
+       WHERE receiver_id = ?1 AND created_at = ?2 AND transaction_id = ?3",
+      params![
+        claim.receiver_id,
+        claim.created_at,
+        claim.transaction_id,
+        (claimed_amount + claim.amount.0).to_string(),
+        claim.status
+      ],
+    )?;
+  }
+  update_account(db, &claim.receiver_id, -(claim.amount.0 as i128), 0)?;
+  record_status_change(
+    db,
+    source,
+    &claim.receiver_id,
+    claim.created_at,
+    &claim.transaction_id,
+    previous.as_ref().map(|(status, _)| status.as_str()),
+    &claim.status,
+    claim.amount.0,
+  )
+}
+
+fn apply_refunded(
+  db: &Transaction,
+  source: &EventSource,
+  refund: &TransactionRefundedData,
+) -> rusqlite::Result<()> {
+  let previous = transaction_status(
+    db,
+    &refund.receiver_id,
+    refund.created_at,
+    &refund.transaction_id,
+  )?;
+  db.execute(
+    "UPDATE transactions SET status = 'REFUND'
+     WHERE receiver_id = ?1 AND created_at = ?2 AND transaction_id = ?3",
+    params![refund.receiver_id, refund.created_at, refund.transaction_id],
+  )?;
+  let amount = refund.amount.0 as i128;
+  update_account(db, &refund.receiver_id, -amount, -amount)?;
+  record_status_change(
+    db,
+    source,
+    &refund.receiver_id,
+    refund.created_at,
+    &refund.transaction_id,
+    previous.as_ref().map(|(status, _)| status.as_str()),
+    "REFUND",
+    refund.amount.0,
+  )
+}
+
+fn apply_reclaimed(
+  db: &Transaction,
+  source: &EventSource,
+  reclaim: &MilestoneData,
+) -> rusqlite::Result<()> {
+  let previous = transaction_status(
+    db,
+    &reclaim.receiver_id,
+    reclaim.created_at,
+    &reclaim.transaction_id,
+  )?;
+  db.execute(
+    "UPDATE transactions SET status = ?4
+     WHERE receiver_id = ?1 AND created_at = ?2 AND transaction_id = ?3",
+    params![
+      reclaim.receiver_id,
+      reclaim.created_at,
+      reclaim.transaction_id,
+      reclaim.status
+    ],
+  )?;
+  let amount = reclaim.amount.0 as i128;
+  update_account(db, &reclaim.receiver_id, -amount, -amount)?;
+  record_status_change(
+    db,
+    source,
+    &reclaim.receiver_id,
+    reclaim.created_at,
+    &reclaim.transaction_id,
+    previous.as_ref().map(|(status, _)| status.as_str()),
+    &reclaim.status,
+    reclaim.amount.0,
+  )
+}
@@ -0,0 +1,212 @@
+// Builds a SQLite ledger from the contract's NEP-297 events and checks it against the chain.
+//
+//   hurdle-index <ledger.db> ingest <receipts.jsonl>
+//   hurdle-index <ledger.db> reconcile <accounts.json>
+//
+// Every input line holds the logs of one receipt: {"receipt_id", "block_height", "logs", "status"}.
+// The reconcile input is a JSON list of `get_account_info` results, mismatches exit with 2.
+use hurdle_payment::AccountJson;
+use near_sdk::serde::Deserialize;
+use near_sdk::{serde_json, Balance};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process::exit;
+
+pub use crate::ledger::*;
+mod ledger;
+pub use crate::reconcile::*;
+mod reconcile;
+
+fn run(args: Vec<String>) -> Result<bool, String> {
+  let (db_path, command, input_path) = match args.as_slice() {
+    [db_path, command, input_path] => (db_path, command, input_path),
+    _ => return Err("Usage: hurdle-index <ledger.db> ingest|reconcile <file>".to_string()),
+  };
+  let mut ledger = Ledger::open(db_path).map_err(|error| error.to_string())?;
+  let input =
+    File::open(input_path).map_err(|error| format!("Cannot read {}: {}", input_path, error))?;
+  match command.as_str() {
+    "ingest" => {
+      let stats = ledger.ingest(BufReader::new(input))?;
+      println!(
+        "{} receipts, {} new events, {} already indexed, {} failed receipts skipped",
+        stats.receipts, stats.events, stats.duplicates, stats.failed_receipts
+      );
+      Ok(true)
+    }
+    "reconcile" => {
+      let snapshots: Vec<AccountJson> = serde_json::from_reader(BufReader::new(input))
+        .map_err(|error| format!("Invalid account snapshots: {}", error))?;
+      let mismatches = reconcile(&ledger, &snapshots)?;
+      for mismatch in mismatches.iter() {
+        println!("{}", mismatch);
+      }
+      Ok(mismatches.is_empty())
+    }
+    command => Err(format!("Unknown command {}", command)),
+  }
+}
+
+fn main() {
+  match run(std::env::args().skip(1).collect()) {
+    Ok(true) => {}
+    Ok(false) => exit(2),
+    Err(error) => {
+      eprintln!("error: {}", error);
+      exit(1);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use hurdle_payment::{HurdlePayment, PendingClaim};
+  use near_sdk::json_types::{ValidAccountId, U128};
+  use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+  use near_sdk::{env, testing_env, MockedBlockchain, PromiseResult};
+
+  const NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+
+  fn receipt(receipt_id: usize, logs: Vec<String>) -> String {
+    serde_json::json!({
+      "receipt_id": format!("r{}", receipt_id),
+      "block_height": receipt_id,
+      "logs": logs,
+    })
+    .to_string()
+  }
+
+  #[test]
+  fn test_ingest_and_reconcile() {
+    let mut context = VMContextBuilder::new();
+    context
+      .current_account_id(accounts(0))
+      .predecessor_account_id(accounts(0))
+      .attached_deposit(10 * NEAR);
+    testing_env!(context.build());
+    let mut contract = HurdlePayment::new();
+    let mut receipts = Vec::new();
+    // Every call is a receipt of its own, callbacks see the given promise results
+    let mut call = |predecessor_id: ValidAccountId,
+                    epoch: u64,
+                    promise_results: Vec<PromiseResult>,
+                    receipts: &mut Vec<String>| {
+      receipts.push(receipt(receipts.len(), get_logs()));
+      testing_env!(
+        context
+          .storage_usage(env::storage_usage())
+          .predecessor_account_id(predecessor_id)
+          .epoch_height(epoch)
+          .block_timestamp(epoch * 1_000 * 1_000_000_000)
+          .build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        promise_results
+      );
+    };
+
+    contract.register_new_account(accounts(1).to_string(), None);
+    call(accounts(2), 0, vec![], &mut receipts);
+    contract.create_campaign("campaign".to_string(), None, None);
+    for transaction_id in ["tx1", "tx2"] {
+      call(accounts(2), 0, vec![], &mut receipts);
+      contract.send_to_contract(
+        accounts(1).to_string(),
+        1.0,
+        1,
+        "campaign".to_string(),
+        transaction_id.to_string(),
+        None,
+        None,
+      );
+    }
+    call(accounts(2), 1, vec![], &mut receipts);
+    contract.refund_by_transaction_id(
+      accounts(2).to_string(),
+      accounts(1).to_string(),
+      "tx1".to_string(),
+      0,
+    );
+    call(accounts(1), 2, vec![], &mut receipts);
+    let claimed = contract
+      .get_account_info(accounts(1).to_string())
+      .locked_balance;
+    contract.claim_and_withdraw(accounts(1).to_string());
+    // The claim is reported by the callback once the payout went through
+    call(
+      accounts(0),
+      2,
+      vec![PromiseResult::Successful(vec![])],
+      &mut receipts,
+    );
+    contract.on_claim_payout_result(
+      accounts(1).to_string(),
+      2,
+      vec![PendingClaim {
+        sender_id: accounts(2).to_string(),
+        organization_id: None,
+        campaign_id: "campaign".to_string(),
+        created_at: 0,
+        transaction_id: "tx2".to_string(),
+        amount: claimed,
+        fee: U128(0),
+        status: "CLAIM".to_string(),
+      }],
+    );
+    call(accounts(2), 2, vec![], &mut receipts);
+    let stream_id = contract.create_stream(accounts(1).to_string(), U128(NEAR / 1_000_000), None);
+    call(accounts(1), 3, vec![], &mut receipts);
+    contract.withdraw_from_stream(stream_id);
+    call(accounts(1), 3, vec![], &mut receipts);
+    // Logs of a failed receipt must not be applied
+    let replayed_logs: Vec<serde_json::Value> = receipts
+      .iter()
+      .flat_map(|line| {
+        let receipt: serde_json::Value = serde_json::from_str(line).unwrap();
+        receipt["logs"].as_array().unwrap().clone()
+      })
+      .collect();
+    receipts.push(
+      serde_json::json!({
+        "receipt_id": "failed",
+        "block_height": 99,
+        "logs": replayed_logs,
+        "status": { "Failure": {} },
+      })
+      .to_string(),
+    );
+    let jsonl = receipts.join("\n");
+
+    let mut ledger = Ledger::open(":memory:").unwrap();
+    let stats = ledger.ingest(jsonl.as_bytes()).unwrap();
+    assert_eq!(stats.events, 7);
+    assert_eq!(stats.failed_receipts, 1);
+    let replay = ledger.ingest(jsonl.as_bytes()).unwrap();
+    assert_eq!(replay.events, 0);
+    assert_eq!(replay.duplicates, 7);
+
+    let statuses: Vec<(String, String)> = ledger
+      .connection()
+      .prepare("SELECT transaction_id, status FROM transactions ORDER BY transaction_id")
+      .unwrap()
+      .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+      .unwrap()
+      .collect::<Result<_, _>>()
+      .unwrap();
+    assert_eq!(
+      statuses,
+      vec![
+        ("tx1".to_string(), "REFUND".to_string()),
+        ("tx2".to_string(), "CLAIM".to_string())
+      ]
+    );
+
+    let snapshot = contract.get_account_info(accounts(1).to_string());
+    assert!(reconcile(&ledger, &[snapshot]).unwrap().is_empty());
+    let mut snapshot = contract.get_account_info(accounts(1).to_string());
+    snapshot.total_revenue.0 += 1;
+    assert_eq!(reconcile(&ledger, &[snapshot]).unwrap().len(), 1);
+  }
+}
@@ -0,0 +1,32 @@
+use crate::*;
+use hurdle_payment::AccountJson;
+
+// Compares the indexed balances against `get_account_info` results taken from the chain
+pub fn reconcile(ledger: &Ledger, snapshots: &[AccountJson]) -> Result<Vec<String>, String> {
+  let mut mismatches = Vec::new();
+  for snapshot in snapshots {
+    let balances = ledger
+      .account_balances(&snapshot.account_id)
+      .map_err(|error| error.to_string())?;
+    let (locked_balance, total_revenue) = match balances {
+      Some(balances) => balances,
+      None => {
+        mismatches.push(format!("{}: not in the ledger", snapshot.account_id));
+        continue;
+      }
+    };
+    if locked_balance != snapshot.locked_balance.0 {
+      mismatches.push(format!(
+        "{}: ledger locked balance {} but the contract reports {}",
+        snapshot.account_id, locked_balance, snapshot.locked_balance.0
+      ));
+    }
+    if total_revenue != snapshot.total_revenue.0 {
+      mismatches.push(format!(
+        "{}: ledger total revenue {} but the contract reports {}",
+        snapshot.account_id, total_revenue, snapshot.total_revenue.0
+      ));
+    }
+  }
+  Ok(mismatches)
+}
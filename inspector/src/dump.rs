@@ -0,0 +1,83 @@
+use crate::*;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{MockedBlockchain, RuntimeFeesConfig, VMConfig};
+
+pub type Storage = HashMap<Vec<u8>, Vec<u8>>;
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct StateItem {
+  key: String,
+  value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct DataRecord {
+  account_id: String,
+  data_key: String,
+  value: String,
+}
+
+// Account, access key and code records of a genesis dump are skipped
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct StateRecord {
+  #[serde(rename = "Data")]
+  data: Option<DataRecord>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", untagged)]
+enum StateDump {
+  ViewState { values: Vec<StateItem> },
+  Pairs(Vec<StateItem>),
+  Records(Vec<StateRecord>),
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, String> {
+  base64::decode(value).map_err(|error| format!("Invalid base64 in {}: {}", field, error))
+}
+
+// Reads a `view_state` RPC response, a plain list of base64 key/value pairs, or the state
+// records of a genesis dump, which hold every account so they need `contract_id`
+pub fn read_state_dump(input: &str, contract_id: Option<&str>) -> Result<Storage, String> {
+  let dump: StateDump =
+    serde_json::from_str(input).map_err(|error| format!("Unrecognized state dump: {}", error))?;
+  let mut storage = HashMap::new();
+  match dump {
+    StateDump::ViewState { values: items } | StateDump::Pairs(items) => {
+      for item in items {
+        storage.insert(decode("key", &item.key)?, decode("value", &item.value)?);
+      }
+    }
+    StateDump::Records(records) => {
+      let contract_id =
+        contract_id.ok_or("State records need --contract-id to pick the contract's data")?;
+      for data in records.into_iter().filter_map(|record| record.data) {
+        if contract_id == data.account_id {
+          storage.insert(
+            decode("data_key", &data.data_key)?,
+            decode("value", &data.value)?,
+          );
+        }
+      }
+    }
+  }
+  Ok(storage)
+}
+
+// Serves the dump as the contract's storage so its own collections can decode it
+pub fn load_storage(storage: Storage) {
+  let mut context = VMContextBuilder::new();
+  context.is_view(true);
+  env::set_blockchain_interface(Box::new(MockedBlockchain::new(
+    context.build(),
+    VMConfig::free(),
+    RuntimeFeesConfig::default(),
+    vec![],
+    storage,
+    HashMap::new(),
+    None,
+  )));
+}
@@ -0,0 +1,63 @@
+use crate::*;
+use hurdle_payment_client::csv_row;
+
+pub fn to_json(report: &StateReport) -> String {
+  serde_json::to_string_pretty(report).unwrap()
+}
+
+pub fn transactions_csv(report: &StateReport) -> String {
+  let mut csv = csv_row(&[
+    "account_id".to_string(),
+    "epoch".to_string(),
+    "transaction_id".to_string(),
+    "sender_id".to_string(),
+    "campaign_id".to_string(),
+    "locked_balance".to_string(),
+    "claimed_amount".to_string(),
+    "status".to_string(),
+    "created_at".to_string(),
+    "claimable_at".to_string(),
+    "refundable_until".to_string(),
+  ]);
+  for account in report.accounts.iter() {
+    for dump in account.transactions.iter() {
+      let transaction = &dump.transaction;
+      csv.push_str(&csv_row(&[
+        account.account.account_id.clone(),
+        dump.epoch.to_string(),
+        transaction.transaction_id.clone(),
+        transaction.sender_id.clone(),
+        transaction.campaign_id.clone(),
+        transaction.locked_balance.0.to_string(),
+        transaction.claimed_amount.0.to_string(),
+        transaction.status.clone(),
+        transaction.created_at.to_string(),
+        transaction.claimable_at.to_string(),
+        transaction.refundable_until.to_string(),
+      ]));
+    }
+  }
+  csv
+}
+
+pub fn accounts_csv(report: &StateReport) -> String {
+  let mut csv = csv_row(&[
+    "account_id".to_string(),
+    "locked_balance".to_string(),
+    "total_revenue".to_string(),
+    "last_unlock_at".to_string(),
+    "kyc_tier".to_string(),
+    "transactions".to_string(),
+  ]);
+  for account in report.accounts.iter() {
+    csv.push_str(&csv_row(&[
+      account.account.account_id.clone(),
+      account.account.locked_balance.0.to_string(),
+      account.account.total_revenue.0.to_string(),
+      account.account.last_unlock_at.to_string(),
+      account.account.kyc_tier.to_string(),
+      account.transactions.len().to_string(),
+    ]));
+  }
+  csv
+}
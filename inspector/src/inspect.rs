@@ -0,0 +1,214 @@
+use crate::*;
+use hurdle_payment::{
+  stored_state_version, Account, AccountJson, HurdlePayment, StorageKey, TransferTransactionJson,
+  STATE_VERSION,
+};
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{AccountId, Balance};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractSummary {
+  pub owner_id: AccountId,
+  pub allowlist_enabled: bool,
+  pub auto_register_enabled: bool,
+  pub retention_period: u64, // epochs
+  pub next_subscription_id: u64,
+  pub next_stream_id: u64,
+  pub next_invoice_id: u64,
+  pub total_escrowed: U128,
+  pub total_locked: U128,
+  pub total_claimed: U128,
+  pub total_refunded: U128,
+  pub total_fees: U128,
+  pub total_prepaid: U128,
+  pub total_streaming: U128,
+  pub total_held_fees: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransactionDump {
+  pub epoch: u64,
+  #[serde(flatten)]
+  pub transaction: TransferTransactionJson,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountDump {
+  #[serde(flatten)]
+  pub account: AccountJson,
+  pub claim_day: u64,
+  pub claimed_today: U128,
+  pub transactions: Vec<TransactionDump>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StateReport {
+  pub state_version: Option<u32>, // None when the state matches no known layout
+  pub contract: Option<ContractSummary>, // only decoded in the current layout
+  pub accounts: Vec<AccountDump>,
+  pub issues: Vec<String>,
+}
+
+impl ContractSummary {
+  fn from(contract: &HurdlePayment) -> Self {
+    let totals = &contract.totals;
+    ContractSummary {
+      owner_id: contract.owner_id.clone(),
+      allowlist_enabled: contract.allowlist_enabled,
+      auto_register_enabled: contract.auto_register_enabled,
+      retention_period: contract.retention_period,
+      next_subscription_id: contract.next_subscription_id,
+      next_stream_id: contract.next_stream_id,
+      next_invoice_id: contract.next_invoice_id,
+      total_escrowed: U128(totals.total_escrowed),
+      total_locked: U128(totals.total_locked),
+      total_claimed: U128(totals.total_claimed),
+      total_refunded: U128(totals.total_refunded),
+      total_fees: U128(totals.total_fees),
+      total_prepaid: U128(totals.total_prepaid),
+      total_streaming: U128(totals.total_streaming),
+      total_held_fees: U128(totals.total_held_fees),
+    }
+  }
+}
+
+// Walks the epoch buckets of an account, the nested maps are read through the contract's
+// own collections from the loaded storage
+fn dump_account(account_id: AccountId, account: Account, issues: &mut Vec<String>) -> AccountDump {
+  let mut transactions = Vec::new();
+  let mut outstanding = 0;
+  for (epoch, bucket) in account.transactions.iter() {
+    for (transaction_id, transaction) in bucket.iter() {
+      let context = format!("{} {}/{}", account_id, epoch, transaction_id);
+      if transaction.receiver_id != account_id {
+        issues.push(format!(
+          "{}: stored under the wrong receiver {}",
+          context, transaction.receiver_id
+        ));
+      }
+      let reclaimed: Balance = transaction
+        .reclaims()
+        .iter()
+        .map(|(_, amount)| amount)
+        .sum();
+      if transaction.claimed_amount + reclaimed > transaction.locked_balance {
+        issues.push(format!("{}: claimed more than was locked", context));
+      }
+      if transaction.refundable_until > transaction.claimable_at {
+        issues.push(format!(
+          "{}: refundable after it becomes claimable",
+          context
+        ));
+      }
+      match transaction.status.as_str() {
+        "LOCK" => {
+          outstanding += transaction
+            .locked_balance
+            .saturating_sub(transaction.claimed_amount + reclaimed)
+        }
+        "CLAIM" if transaction.claimed_amount + reclaimed != transaction.locked_balance => {
+          issues.push(format!("{}: claimed but not fully paid out", context))
+        }
+        "REFUND" if transaction.claimed_amount != 0 => {
+          issues.push(format!("{}: refunded after a partial claim", context))
+        }
+        "CLAIM" | "REFUND" => {}
+        status => issues.push(format!("{}: unknown status {}", context, status)),
+      }
+      transactions.push(TransactionDump {
+        epoch,
+        transaction: TransferTransactionJson::from(transaction_id, transaction),
+      });
+    }
+  }
+  if outstanding != account.locked_balance {
+    issues.push(format!(
+      "{}: locked balance {} does not match {} outstanding in its transactions",
+      account_id, account.locked_balance, outstanding
+    ));
+  }
+  AccountDump {
+    claim_day: account.claim_day,
+    claimed_today: U128(account.claimed_today),
+    account: AccountJson::from(account_id, account),
+    transactions,
+  }
+}
+
+pub fn inspect(storage: Storage) -> Result<StateReport, String> {
+  let state = storage
+    .get(b"STATE".as_ref())
+    .ok_or("No contract state in the dump")?;
+  let state_version = stored_state_version(state);
+  if state_version != Some(STATE_VERSION) {
+    let issue = match state_version {
+      Some(0) => "Contract state is version 0, run `migrate` before inspecting it".to_string(),
+      Some(state_version) => format!(
+        "Contract state version {} is not supported, expected {}",
+        state_version, STATE_VERSION
+      ),
+      None => "Contract state matches no known layout".to_string(),
+    };
+    return Ok(StateReport {
+      state_version,
+      contract: None,
+      accounts: Vec::new(),
+      issues: vec![issue],
+    });
+  }
+
+  let prefix = StorageKey::AccountKey.try_to_vec().unwrap();
+  let mut issues = Vec::new();
+  let mut accounts = Vec::new();
+  for (key, value) in storage.iter().filter(|(key, _)| key.starts_with(&prefix)) {
+    let account_id = AccountId::try_from_slice(&key[prefix.len()..]);
+    let account = Account::try_from_slice(value);
+    match (account_id, account) {
+      (Ok(account_id), Ok(account)) => accounts.push((account_id, account)),
+      _ => issues.push(format!("Undecodable account entry {}", base64::encode(key))),
+    }
+  }
+  accounts.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+  load_storage(storage);
+  let contract: HurdlePayment = env::state_read().ok_or("No contract state in the dump")?;
+
+  let mut account_dumps = Vec::new();
+  let mut total_locked = 0;
+  for (account_id, account) in accounts {
+    total_locked += account.locked_balance;
+    let mut account_issues = Vec::new();
+    let dump = catch_unwind(AssertUnwindSafe(|| {
+      dump_account(account_id.clone(), account, &mut account_issues)
+    }));
+    issues.append(&mut account_issues);
+    match dump {
+      Ok(dump) => account_dumps.push(dump),
+      Err(_) => issues.push(format!("{}: transaction maps are inconsistent", account_id)),
+    }
+  }
+
+  let totals = &contract.totals;
+  if totals.total_locked != total_locked {
+    issues.push(format!(
+      "Contract total locked {} does not match {} locked across accounts",
+      totals.total_locked, total_locked
+    ));
+  }
+  if totals.total_escrowed != totals.total_locked + totals.total_claimed + totals.total_refunded {
+    issues.push("Escrowed total is not locked + claimed + refunded".to_string());
+  }
+
+  Ok(StateReport {
+    state_version,
+    contract: Some(ContractSummary::from(&contract)),
+    accounts: account_dumps,
+    issues,
+  })
+}
@@ -0,0 +1,219 @@
+// Decodes a state dump of the hurdle-payment contract and checks it for inconsistencies.
+//
+//   hurdle-inspect <dump.json> [--format json|csv|accounts-csv] [--contract-id ID] [--output FILE]
+//
+// --contract-id is required for the state records of a genesis dump.
+//
+// Exits with 2 when the integrity checks find issues, they are also printed to stderr.
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{base64, env, serde_json};
+use std::collections::HashMap;
+use std::process::exit;
+
+pub use crate::dump::*;
+mod dump;
+pub use crate::inspect::*;
+mod inspect;
+pub use crate::export::*;
+mod export;
+
+struct Options {
+  path: String,
+  format: String,
+  contract_id: Option<String>,
+  output: Option<String>,
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+  let mut options = Options {
+    path: String::new(),
+    format: "json".to_string(),
+    contract_id: None,
+    output: None,
+  };
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    let mut value = |name: &str| args.next().ok_or(format!("Missing value for {}", name));
+    match arg.as_str() {
+      "--format" => options.format = value("--format")?,
+      "--contract-id" => options.contract_id = Some(value("--contract-id")?),
+      "--output" => options.output = Some(value("--output")?),
+      _ if options.path.is_empty() && !arg.starts_with("--") => options.path = arg,
+      _ => return Err(format!("Unexpected argument {}", arg)),
+    }
+  }
+  if options.path.is_empty() {
+    return Err("Missing the state dump path".to_string());
+  }
+  Ok(options)
+}
+
+fn run(options: Options) -> Result<bool, String> {
+  let input = std::fs::read_to_string(&options.path)
+    .map_err(|error| format!("Cannot read {}: {}", options.path, error))?;
+  let storage = read_state_dump(&input, options.contract_id.as_deref())?;
+  let report = inspect(storage)?;
+  let output = match options.format.as_str() {
+    "json" => to_json(&report),
+    "csv" => transactions_csv(&report),
+    "accounts-csv" => accounts_csv(&report),
+    format => return Err(format!("Unknown format {}", format)),
+  };
+  match &options.output {
+    Some(path) => {
+      std::fs::write(path, output).map_err(|error| format!("Cannot write {}: {}", path, error))?
+    }
+    None => print!("{}", output),
+  }
+  for issue in report.issues.iter() {
+    eprintln!("issue: {}", issue);
+  }
+  Ok(report.issues.is_empty())
+}
+
+fn main() {
+  let result = parse_options(std::env::args().skip(1).collect()).and_then(run);
+  match result {
+    Ok(true) => {}
+    Ok(false) => exit(2),
+    Err(error) => {
+      eprintln!("error: {}", error);
+      exit(1);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use hurdle_payment::{HurdlePayment, StorageKey, STATE_VERSION};
+  use near_sdk::collections::LookupMap;
+  use near_sdk::test_utils::{accounts, VMContextBuilder};
+  use near_sdk::{testing_env, MockedBlockchain};
+
+  const NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+
+  // Runs a few payments in the mocked blockchain and returns its storage as a view_state dump
+  fn state_dump() -> String {
+    let mut context = VMContextBuilder::new();
+    context
+      .current_account_id(accounts(0))
+      .predecessor_account_id(accounts(0))
+      .attached_deposit(10 * NEAR);
+    testing_env!(context.build());
+    let mut contract = HurdlePayment::new();
+    contract.register_new_account(accounts(1).to_string(), None);
+    testing_env!(context
+      .storage_usage(env::storage_usage())
+      .predecessor_account_id(accounts(2))
+      .build());
+    for transaction_id in ["tx1", "tx,2"] {
+      contract.send_to_contract(
+        accounts(1).to_string(),
+        1.0,
+        1,
+        "campaign".to_string(),
+        transaction_id.to_string(),
+        None,
+        None,
+      );
+    }
+    env::state_write(&contract);
+
+    let mut blockchain = env::take_blockchain_interface().unwrap();
+    let storage = blockchain
+      .as_mut_mocked_blockchain()
+      .unwrap()
+      .take_storage();
+    let values: Vec<_> = storage
+      .iter()
+      .map(|(key, value)| {
+        serde_json::json!({ "key": base64::encode(key), "value": base64::encode(value) })
+      })
+      .collect();
+    serde_json::json!({ "values": values, "proof": [] }).to_string()
+  }
+
+  #[test]
+  fn test_inspect_state_dump() {
+    let storage = read_state_dump(&state_dump(), None).unwrap();
+    let report = inspect(storage).unwrap();
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+    let contract = report.contract.as_ref().unwrap();
+    assert_eq!(report.state_version, Some(STATE_VERSION));
+    assert_eq!(contract.owner_id, accounts(0).to_string());
+    assert_eq!(report.accounts.len(), 1);
+    assert_eq!(report.accounts[0].transactions.len(), 2);
+    assert_eq!(
+      report.accounts[0].account.locked_balance.0,
+      contract.total_locked.0
+    );
+
+    let csv = transactions_csv(&report);
+    assert_eq!(csv.lines().count(), 3);
+    assert!(csv.contains("\"tx,2\""));
+  }
+
+  #[test]
+  fn test_read_state_records() {
+    let dump: serde_json::Value = serde_json::from_str(&state_dump()).unwrap();
+    let mut records: Vec<_> = dump["values"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|item| {
+        serde_json::json!({ "Data": {
+          "account_id": accounts(0).to_string(),
+          "data_key": item["key"],
+          "value": item["value"],
+        }})
+      })
+      .collect();
+    records.push(serde_json::json!({ "Data": {
+      "account_id": accounts(3).to_string(),
+      "data_key": base64::encode(b"STATE"),
+      "value": base64::encode(b"other"),
+    }}));
+    let records = serde_json::Value::Array(records).to_string();
+
+    assert!(read_state_dump(&records, None)
+      .unwrap_err()
+      .contains("--contract-id"));
+    let storage = read_state_dump(&records, Some(accounts(0).as_ref())).unwrap();
+    assert!(inspect(storage).unwrap().issues.is_empty());
+  }
+
+  #[test]
+  fn test_inspect_reports_mismatched_totals() {
+    let mut storage = read_state_dump(&state_dump(), None).unwrap();
+    load_storage(storage.clone());
+    let mut contract: HurdlePayment = env::state_read().unwrap();
+    contract.totals.total_locked += 1;
+    storage.insert(
+      b"STATE".to_vec(),
+      near_sdk::borsh::BorshSerialize::try_to_vec(&contract).unwrap(),
+    );
+
+    let report = inspect(storage).unwrap();
+    assert_eq!(report.issues.len(), 2);
+    assert!(report.issues[0].starts_with("Contract total locked"));
+  }
+
+  #[test]
+  fn test_inspect_reports_version_0_state() {
+    let mut storage = read_state_dump(&state_dump(), None).unwrap();
+    // Version 0 held the owner and the accounts map only
+    let accounts_map: LookupMap<String, u8> = LookupMap::new(StorageKey::AccountKey);
+    storage.insert(
+      b"STATE".to_vec(),
+      near_sdk::borsh::BorshSerialize::try_to_vec(&(accounts(0).to_string(), accounts_map))
+        .unwrap(),
+    );
+
+    let report = inspect(storage).unwrap();
+    assert_eq!(report.state_version, Some(0));
+    assert!(report.contract.is_none());
+    assert_eq!(report.issues.len(), 1);
+    assert!(report.issues[0].contains("version 0"));
+  }
+}
@@ -0,0 +1,111 @@
+//! Helpers for running the contract in a local NEAR sandbox.
+//!
+//! The sandbox binary is taken from `NEAR_SANDBOX_BIN_PATH` or downloaded on first use, so
+//! the tests are ignored by default: `cargo test -- --ignored` from this directory.
+
+use near_workspaces::network::Sandbox;
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, Contract, Worker};
+use serde_json::json;
+use std::path::PathBuf;
+use std::process::Command;
+
+pub const ONE_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+
+/// Builds the contract with `build.sh` and returns the wasm it copies to `out/`.
+pub fn build_contract_wasm() -> anyhow::Result<Vec<u8>> {
+  let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..");
+  let status = Command::new("bash")
+    .arg("build.sh")
+    .current_dir(&root)
+    .status()?;
+  anyhow::ensure!(status.success(), "build.sh failed with {}", status);
+  Ok(std::fs::read(root.join("out/hurdle-payment.wasm"))?)
+}
+
+/// Builds the hook receiver and milestone oracle stand-in from `test-receiver/`.
+pub fn build_test_receiver_wasm() -> anyhow::Result<Vec<u8>> {
+  let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  let target_dir = root.join("target/test-receiver");
+  let status = Command::new("cargo")
+    .args(["build", "--target", "wasm32-unknown-unknown", "--release"])
+    .arg("--target-dir")
+    .arg(&target_dir)
+    .env("RUSTFLAGS", "-C link-arg=-s")
+    .current_dir(root.join("test-receiver"))
+    .status()?;
+  anyhow::ensure!(
+    status.success(),
+    "building test-receiver failed with {}",
+    status
+  );
+  Ok(std::fs::read(target_dir.join(
+    "wasm32-unknown-unknown/release/hurdle_payment_test_receiver.wasm",
+  ))?)
+}
+
+/// Deploys a test receiver that accepts or rejects claimed payments and answers milestone
+/// oracle checks with `milestone_met`.
+pub async fn deploy_test_receiver(
+  worker: &Worker<Sandbox>,
+  accept_payments: bool,
+  milestone_met: bool,
+) -> anyhow::Result<Contract> {
+  let contract = worker.dev_deploy(&build_test_receiver_wasm()?).await?;
+  contract
+    .call("new")
+    .args_json(json!({
+      "accept_payments": accept_payments,
+      "milestone_met": milestone_met,
+    }))
+    .transact()
+    .await?
+    .into_result()?;
+  Ok(contract)
+}
+
+pub struct TestEnv {
+  pub worker: Worker<Sandbox>,
+  pub contract: Contract,
+  pub sender: Account,
+  pub receiver: Account,
+  // Calls claims and refunds so the gas never blurs the payer or payee balances
+  pub operator: Account,
+}
+
+/// Deploys and initializes the contract, and registers a funded receiver account.
+pub async fn setup() -> anyhow::Result<TestEnv> {
+  let worker = near_workspaces::sandbox().await?;
+  let contract = worker.dev_deploy(&build_contract_wasm()?).await?;
+  contract.call("new").transact().await?.into_result()?;
+  let sender = worker.dev_create_account().await?;
+  let receiver = worker.dev_create_account().await?;
+  let operator = worker.dev_create_account().await?;
+  register(&sender, &contract, receiver.id().as_str()).await?;
+  Ok(TestEnv {
+    worker,
+    contract,
+    sender,
+    receiver,
+    operator,
+  })
+}
+
+pub async fn register(
+  caller: &Account,
+  contract: &Contract,
+  account_id: &str,
+) -> anyhow::Result<()> {
+  caller
+    .call(contract.id(), "register_new_account")
+    .args_json(json!({ "account_id": account_id }))
+    .deposit(NearToken::from_near(1))
+    .transact()
+    .await?
+    .into_result()?;
+  Ok(())
+}
+
+pub async fn balance(account: &Account) -> anyhow::Result<u128> {
+  Ok(account.view_account().await?.balance.as_yoctonear())
+}
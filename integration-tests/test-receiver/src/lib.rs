@@ -0,0 +1,58 @@
+//! Stand-in for a hook-enabled receiver contract and a milestone oracle, deployed next to the
+//! escrow by the sandbox tests.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, AccountId, PanicOnDefault};
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimedPayment {
+  pub created_at: u64,
+  pub transaction_id: String,
+  pub amount: U128,
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct TestReceiver {
+  accept_payments: bool,
+  milestone_met: bool,
+  received: Vec<String>, // transaction ids of the payments the hook took
+}
+
+#[near_bindgen]
+impl TestReceiver {
+  #[init]
+  pub fn new(accept_payments: bool, milestone_met: bool) -> Self {
+    TestReceiver {
+      accept_payments,
+      milestone_met,
+      received: Vec::new(),
+    }
+  }
+
+  // Failing sends the attached payout back to the escrow
+  #[payable]
+  pub fn on_hurdle_payment_claimed(&mut self, payments: Vec<ClaimedPayment>) {
+    assert!(self.accept_payments, "Payments are not accepted");
+    self
+      .received
+      .extend(payments.into_iter().map(|payment| payment.transaction_id));
+  }
+
+  #[allow(unused_variables)]
+  pub fn is_milestone_met(
+    &self,
+    receiver_id: AccountId,
+    transaction_id: String,
+    milestone_index: u64,
+  ) -> bool {
+    self.milestone_met
+  }
+
+  pub fn get_received(&self) -> Vec<String> {
+    self.received.clone()
+  }
+}
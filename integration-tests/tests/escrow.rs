@@ -0,0 +1,324 @@
+use hurdle_payment_integration_tests::*;
+use near_workspaces::result::ExecutionFinalResult;
+use near_workspaces::types::NearToken;
+use near_workspaces::Contract;
+use serde_json::{json, Value};
+
+// 1.0 converted by send_to_contract's f64 amount
+const ONE_NEAR_SENT: u128 = 999_999_999_999_999_983_222_784;
+
+async fn send(
+  env: &TestEnv,
+  receiver_id: &str,
+  cash_hold_time: i64,
+  transaction_id: &str,
+) -> anyhow::Result<()> {
+  env
+    .sender
+    .call(env.contract.id(), "send_to_contract")
+    .args_json(json!({
+      "receiver_id": receiver_id,
+      "y_amount": 1.0,
+      "cash_hold_time": cash_hold_time,
+      "campaign_id": "1",
+      "transaction_id": transaction_id,
+    }))
+    .deposit(NearToken::from_near(2))
+    .max_gas()
+    .transact()
+    .await?
+    .into_result()?;
+  Ok(())
+}
+
+async fn account_info(env: &TestEnv, account_id: &str) -> anyhow::Result<Value> {
+  Ok(
+    env
+      .contract
+      .view("get_account_info")
+      .args_json(json!({ "account_id": account_id }))
+      .await?
+      .json()?,
+  )
+}
+
+#[tokio::test]
+#[ignore = "requires a NEAR sandbox"]
+async fn test_deposit_and_claim_move_funds() -> anyhow::Result<()> {
+  let env = setup().await?;
+  let receiver_id = env.receiver.id().to_string();
+
+  let sender_before = balance(&env.sender).await?;
+  send(&env, &receiver_id, 0, "test").await?;
+  // The sender pays the amount, storage and gas, the rest of the deposit comes back
+  let sender_spent = sender_before - balance(&env.sender).await?;
+  assert!(sender_spent > ONE_NEAR_SENT && sender_spent < ONE_NEAR_SENT + ONE_NEAR / 10);
+  assert_eq!(
+    account_info(&env, &receiver_id).await?["locked_balance"],
+    ONE_NEAR_SENT.to_string()
+  );
+
+  let receiver_before = balance(&env.receiver).await?;
+  env
+    .operator
+    .call(env.contract.id(), "claim_and_withdraw")
+    .args_json(json!({ "account_id": receiver_id }))
+    .deposit(NearToken::from_millinear(10))
+    .max_gas()
+    .transact()
+    .await?
+    .into_result()?;
+  assert_eq!(
+    balance(&env.receiver).await? - receiver_before,
+    ONE_NEAR_SENT
+  );
+  assert_eq!(
+    account_info(&env, &receiver_id).await?["locked_balance"],
+    "0"
+  );
+  Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires a NEAR sandbox"]
+async fn test_refund_returns_funds_to_sender() -> anyhow::Result<()> {
+  let env = setup().await?;
+  let receiver_id = env.receiver.id().to_string();
+  send(&env, &receiver_id, 1, "test").await?;
+  let registered_at = account_info(&env, &receiver_id).await?["last_unlock_at"]
+    .as_u64()
+    .unwrap();
+  let transactions: Value = env
+    .contract
+    .view("get_transactions_info")
+    .args_json(json!({
+      "account_id": receiver_id,
+      "start_epoch": registered_at,
+      "end_epoch": registered_at + 10,
+    }))
+    .await?
+    .json()?;
+  let created_at = transactions[0]["created_at"].as_u64().unwrap();
+
+  let sender_before = balance(&env.sender).await?;
+  env
+    .sender
+    .call(env.contract.id(), "refund_by_transaction_id")
+    .args_json(json!({
+      "sender_id": env.sender.id(),
+      "receiver_id": receiver_id,
+      "transaction_id": "test",
+      "create_epoch": created_at,
+    }))
+    .max_gas()
+    .transact()
+    .await?
+    .into_result()?;
+  // Only the sender can refund, the call's gas comes out of the refund it gets back
+  let refunded = balance(&env.sender).await? - sender_before;
+  assert!(refunded <= ONE_NEAR_SENT && refunded > ONE_NEAR_SENT - ONE_NEAR / 100);
+  let account = account_info(&env, &receiver_id).await?;
+  assert_eq!(account["locked_balance"], "0");
+  assert_eq!(account["total_revenue"], "0");
+  Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires a NEAR sandbox"]
+async fn test_failed_claim_transfer_keeps_funds_claimable() -> anyhow::Result<()> {
+  let env = setup().await?;
+  // A valid account id that was never created on chain
+  let ghost_id = format!("ghost.{}", env.worker.root_account()?.id());
+  register(&env.sender, &env.contract, &ghost_id).await?;
+  send(&env, &ghost_id, 0, "test").await?;
+
+  let outcome = claim(&env, &ghost_id).await?;
+  assert!(!outcome.receipt_failures().is_empty());
+  // The transfer came back, so the payment is locked again instead of counted as claimed
+  assert_eq!(
+    account_info(&env, &ghost_id).await?["locked_balance"],
+    ONE_NEAR_SENT.to_string()
+  );
+  let reconciliation = reconciliation(&env).await?;
+  assert_eq!(reconciliation["total_claimed"], "0");
+  assert_eq!(reconciliation["total_locked"], ONE_NEAR_SENT.to_string());
+  Ok(())
+}
+
+async fn claim(env: &TestEnv, account_id: &str) -> anyhow::Result<ExecutionFinalResult> {
+  Ok(
+    env
+      .operator
+      .call(env.contract.id(), "claim_and_withdraw")
+      .args_json(json!({ "account_id": account_id }))
+      .deposit(NearToken::from_millinear(10))
+      .max_gas()
+      .transact()
+      .await?,
+  )
+}
+
+async fn reconciliation(env: &TestEnv) -> anyhow::Result<Value> {
+  Ok(
+    env
+      .contract
+      .view("get_reconciliation")
+      .args_json(json!({}))
+      .await?
+      .json()?,
+  )
+}
+
+// Registers the test receiver and lets it turn on its own claim hook
+async fn register_hook_receiver(env: &TestEnv, hook: &Contract) -> anyhow::Result<()> {
+  register(&env.sender, &env.contract, hook.id().as_str()).await?;
+  hook
+    .as_account()
+    .call(env.contract.id(), "set_claim_hook_enabled")
+    .args_json(json!({ "enabled": true }))
+    .deposit(NearToken::from_yoctonear(1))
+    .transact()
+    .await?
+    .into_result()?;
+  Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires a NEAR sandbox"]
+async fn test_claim_hook_receives_payments() -> anyhow::Result<()> {
+  let env = setup().await?;
+  let hook = deploy_test_receiver(&env.worker, true, false).await?;
+  let hook_id = hook.id().to_string();
+  register_hook_receiver(&env, &hook).await?;
+  send(&env, &hook_id, 0, "test").await?;
+
+  let hook_before = balance(hook.as_account()).await?;
+  claim(&env, &hook_id).await?.into_result()?;
+  // The payout is the hook call's deposit, the hook also earns a share of its own gas
+  let received = balance(hook.as_account()).await? - hook_before;
+  assert!((ONE_NEAR_SENT..ONE_NEAR_SENT + ONE_NEAR / 100).contains(&received));
+  let payments: Vec<String> = hook.view("get_received").await?.json()?;
+  assert_eq!(payments, vec!["test".to_string()]);
+  assert_eq!(account_info(&env, &hook_id).await?["locked_balance"], "0");
+  assert_eq!(
+    reconciliation(&env).await?["total_claimed"],
+    ONE_NEAR_SENT.to_string()
+  );
+  Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires a NEAR sandbox"]
+async fn test_failed_claim_hook_keeps_funds_claimable() -> anyhow::Result<()> {
+  let env = setup().await?;
+  let hook = deploy_test_receiver(&env.worker, false, false).await?;
+  let hook_id = hook.id().to_string();
+  register_hook_receiver(&env, &hook).await?;
+  send(&env, &hook_id, 0, "test").await?;
+
+  let hook_before = balance(hook.as_account()).await?;
+  let outcome = claim(&env, &hook_id).await?;
+  assert!(!outcome.receipt_failures().is_empty());
+  // The rejected deposit went back to the contract and the payment is locked again
+  assert!(balance(hook.as_account()).await? - hook_before < ONE_NEAR / 100);
+  let payments: Vec<String> = hook.view("get_received").await?.json()?;
+  assert!(payments.is_empty());
+  assert_eq!(
+    account_info(&env, &hook_id).await?["locked_balance"],
+    ONE_NEAR_SENT.to_string()
+  );
+  let reconciliation = reconciliation(&env).await?;
+  assert_eq!(reconciliation["total_claimed"], "0");
+  assert_eq!(reconciliation["total_locked"], ONE_NEAR_SENT.to_string());
+  Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires a NEAR sandbox"]
+async fn test_milestone_oracle_callback() -> anyhow::Result<()> {
+  let env = setup().await?;
+  let receiver_id = env.receiver.id().to_string();
+  let registered_at = account_info(&env, &receiver_id).await?["last_unlock_at"]
+    .as_u64()
+    .unwrap();
+  for (transaction_id, milestone_met) in [("met", true), ("not-met", false)] {
+    let oracle = deploy_test_receiver(&env.worker, true, milestone_met).await?;
+    env
+      .sender
+      .call(env.contract.id(), "send_to_contract")
+      .args_json(json!({
+        "receiver_id": receiver_id,
+        "y_amount": 1.0,
+        "cash_hold_time": 0,
+        "campaign_id": "1",
+        "transaction_id": transaction_id,
+        "schedule": { "Milestones": { "milestones": [{
+          "share_bps": 10_000,
+          "approver_id": oracle.id(),
+          "oracle": true,
+          "deadline": 10,
+        }] } },
+      }))
+      .deposit(NearToken::from_near(2))
+      .max_gas()
+      .transact()
+      .await?
+      .into_result()?;
+    let transactions: Value = env
+      .contract
+      .view("get_transactions_info")
+      .args_json(json!({
+        "account_id": receiver_id,
+        "start_epoch": registered_at,
+        "end_epoch": registered_at + 10,
+      }))
+      .await?
+      .json()?;
+    let created_at = transactions
+      .as_array()
+      .unwrap()
+      .iter()
+      .find(|transaction| transaction["transaction_id"] == transaction_id)
+      .unwrap()["created_at"]
+      .as_u64()
+      .unwrap();
+
+    // The callback's answer is the outcome of the whole call
+    let released: bool = env
+      .operator
+      .call(env.contract.id(), "check_milestone_oracle")
+      .args_json(json!({
+        "receiver_id": receiver_id,
+        "create_epoch": created_at,
+        "transaction_id": transaction_id,
+        "milestone_index": 0,
+      }))
+      .max_gas()
+      .transact()
+      .await?
+      .into_result()?
+      .json()?;
+    assert_eq!(released, milestone_met);
+    let transactions: Value = env
+      .contract
+      .view("get_transactions_info")
+      .args_json(json!({
+        "account_id": receiver_id,
+        "start_epoch": created_at,
+        "end_epoch": created_at,
+      }))
+      .await?
+      .json()?;
+    let transaction = transactions
+      .as_array()
+      .unwrap()
+      .iter()
+      .find(|transaction| transaction["transaction_id"] == transaction_id)
+      .unwrap();
+    assert_eq!(
+      transaction["schedule"]["Milestones"]["milestones"][0]["released_at"].is_u64(),
+      milestone_met
+    );
+  }
+  Ok(())
+}
@@ -7,6 +7,10 @@ pub struct Account {
   pub total_revenue: Balance,
   pub transactions: UnorderedMap<u64, UnorderedMap<String, TransferTransaction>>, // date_string: { transaction_id: TransferTransaction}
   pub last_unlock_at: u64,
+  pub kyc_tier: u8,
+  pub claim_day: u64,
+  pub claimed_today: Balance,
+  pub claim_hook_enabled: bool, // claims call the receiver's `on_hurdle_payment_claimed`
 }
 
 #[derive(Deserialize, Serialize)]
@@ -16,6 +20,8 @@ pub struct AccountJson {
   pub locked_balance: U128,
   pub total_revenue: U128,
   pub last_unlock_at: u64,
+  pub kyc_tier: u8,
+  pub claim_hook_enabled: bool,
 }
 
 impl AccountJson {
@@ -25,8 +31,19 @@ impl AccountJson {
       locked_balance: U128(account.locked_balance),
       total_revenue: U128(account.total_revenue),
       last_unlock_at: account.last_unlock_at,
+      kyc_tier: account.kyc_tier,
+      claim_hook_enabled: account.claim_hook_enabled,
     }
   }
 }
 
 // code .
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountScreeningJson {
+  pub account_id: AccountId,
+  pub blocked_sender: bool,
+  pub blocked_receiver: bool,
+  pub allowlisted: bool,
+}
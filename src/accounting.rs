@@ -0,0 +1,63 @@
+use crate::*;
+use near_sdk::json_types::I128;
+
+// Running totals of the NEAR the contract holds on behalf of its users
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct AccountingTotals {
+  pub total_escrowed: Balance,
+  pub total_locked: Balance,
+  pub total_claimed: Balance,
+  pub total_refunded: Balance,
+  pub total_fees: Balance,
+  pub total_prepaid: Balance,
+  pub total_streaming: Balance,
+  pub total_held_fees: Balance, // paid with locked payments, earned once they are claimed
+}
+
+impl AccountingTotals {
+  pub fn liabilities(&self) -> Balance {
+    self.total_locked
+      + self.total_fees
+      + self.total_prepaid
+      + self.total_streaming
+      + self.total_held_fees
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReconciliationJson {
+  pub total_escrowed: U128,
+  pub total_locked: U128,
+  pub total_claimed: U128,
+  pub total_refunded: U128,
+  pub total_fees: U128,
+  pub total_prepaid: U128,
+  pub total_streaming: U128,
+  pub total_held_fees: U128,
+  pub storage_reserve: U128,
+  pub account_balance: U128,
+  // account_balance - liabilities - storage_reserve, negative when the contract is short
+  pub surplus: I128,
+}
+
+impl ReconciliationJson {
+  pub fn from(totals: &AccountingTotals) -> Self {
+    let storage_reserve = env::storage_byte_cost() * Balance::from(env::storage_usage());
+    let account_balance = env::account_balance();
+    let surplus = account_balance as i128 - totals.liabilities() as i128 - storage_reserve as i128;
+    ReconciliationJson {
+      total_escrowed: U128(totals.total_escrowed),
+      total_locked: U128(totals.total_locked),
+      total_claimed: U128(totals.total_claimed),
+      total_refunded: U128(totals.total_refunded),
+      total_fees: U128(totals.total_fees),
+      total_prepaid: U128(totals.total_prepaid),
+      total_streaming: U128(totals.total_streaming),
+      total_held_fees: U128(totals.total_held_fees),
+      storage_reserve: U128(storage_reserve),
+      account_balance: U128(account_balance),
+      surplus: I128(surplus),
+    }
+  }
+}
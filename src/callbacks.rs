@@ -0,0 +1,20 @@
+use crate::*;
+use near_sdk::ext_contract;
+
+// Callbacks the contract schedules on itself after its cross-contract calls
+#[ext_contract(ext_self)]
+pub trait SelfCallbacks {
+  fn on_milestone_oracle_result(
+    &mut self,
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+    milestone_index: u64,
+  ) -> bool;
+  fn on_claim_payout_result(
+    &mut self,
+    account_id: AccountId,
+    claimed_at: u64,
+    claims: Vec<PendingClaim>,
+  ) -> bool;
+}
@@ -0,0 +1,163 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Campaign {
+  pub owner_id: AccountId,
+  pub refund_window: Option<u64>, // default refund window in epochs
+  pub approval_policy: Option<ApprovalPolicy>,
+  pub organization_id: Option<String>, // managed by the organization's admins instead of the owner
+}
+
+// Payments of at least `threshold` need `required_approvals` of the approvers before they unlock
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ApprovalPolicy {
+  pub threshold: Balance,
+  pub required_approvals: u8,
+  pub approvers: Vec<AccountId>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ApprovalPolicyJson {
+  pub threshold: U128,
+  pub required_approvals: u8,
+  pub approvers: Vec<AccountId>,
+}
+
+impl ApprovalPolicy {
+  pub fn from(policy: ApprovalPolicyJson) -> Self {
+    let mut approvers = policy.approvers.clone();
+    approvers.sort();
+    approvers.dedup();
+    assert_eq!(
+      approvers.len(),
+      policy.approvers.len(),
+      "Approvers must be unique"
+    );
+    assert!(
+      policy.required_approvals >= 1 && policy.required_approvals as usize <= approvers.len(),
+      "Required approvals must be between 1 and the number of approvers"
+    );
+    ApprovalPolicy {
+      threshold: policy.threshold.0,
+      required_approvals: policy.required_approvals,
+      approvers: policy.approvers,
+    }
+  }
+}
+
+impl ApprovalPolicyJson {
+  pub fn from(policy: ApprovalPolicy) -> Self {
+    ApprovalPolicyJson {
+      threshold: U128(policy.threshold),
+      required_approvals: policy.required_approvals,
+      approvers: policy.approvers,
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CampaignJson {
+  pub campaign_id: String,
+  pub owner_id: AccountId,
+  pub refund_window: Option<u64>,
+  pub approval_policy: Option<ApprovalPolicyJson>,
+  pub organization_id: Option<String>,
+}
+
+impl CampaignJson {
+  pub fn from(campaign_id: String, campaign: Campaign) -> Self {
+    CampaignJson {
+      campaign_id,
+      owner_id: campaign.owner_id,
+      refund_window: campaign.refund_window,
+      approval_policy: campaign.approval_policy.map(ApprovalPolicyJson::from),
+      organization_id: campaign.organization_id,
+    }
+  }
+}
+
+// Running aggregates of every transaction created under a campaign id
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CampaignStats {
+  pub transaction_count: u64,
+  pub refunded_count: u64,
+  pub total_locked: Balance,
+  pub total_claimed: Balance,
+  pub total_refunded: Balance,
+  pub total_hold_time: u64,                        // epochs
+  pub receivers: UnorderedMap<AccountId, Balance>, // net amount locked for each receiver
+  pub ranking: TreeMap<(Balance, AccountId), ()>,  // (Balance::MAX - amount, receiver), top first
+}
+
+impl CampaignStats {
+  pub fn new(campaign_id: &str) -> Self {
+    CampaignStats {
+      transaction_count: 0,
+      refunded_count: 0,
+      total_locked: 0,
+      total_claimed: 0,
+      total_refunded: 0,
+      total_hold_time: 0,
+      receivers: UnorderedMap::new(StorageKey::CampaignReceivers {
+        campaign_hash: env::sha256(campaign_id.as_bytes()),
+      }),
+      ranking: TreeMap::new(StorageKey::CampaignRanking {
+        campaign_hash: env::sha256(campaign_id.as_bytes()),
+      }),
+    }
+  }
+
+  pub fn received(&self, receiver_id: &AccountId) -> Balance {
+    self.receivers.get(receiver_id).unwrap_or(0)
+  }
+
+  // Moves the receiver to its new place in the ranking
+  pub fn set_received(&mut self, receiver_id: &AccountId, amount: Balance) {
+    if let Some(previous) = self.receivers.insert(receiver_id, &amount) {
+      self
+        .ranking
+        .remove(&(Balance::MAX - previous, receiver_id.clone()));
+    }
+    self
+      .ranking
+      .insert(&(Balance::MAX - amount, receiver_id.clone()), &());
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CampaignStatsJson {
+  pub campaign_id: String,
+  pub transaction_count: u64,
+  pub total_locked: U128,
+  pub total_claimed: U128,
+  pub total_refunded: U128,
+  pub unique_receivers: u64,
+  pub average_hold_time: u64, // epochs
+  pub refund_rate_bps: u64,   // refunded transactions out of all, in basis points
+}
+
+impl CampaignStatsJson {
+  pub fn from(campaign_id: String, stats: &CampaignStats) -> Self {
+    let per_transaction = |total: u64| total.checked_div(stats.transaction_count).unwrap_or(0);
+    CampaignStatsJson {
+      campaign_id,
+      transaction_count: stats.transaction_count,
+      total_locked: U128(stats.total_locked),
+      total_claimed: U128(stats.total_claimed),
+      total_refunded: U128(stats.total_refunded),
+      unique_receivers: stats.receivers.len(),
+      average_hold_time: per_transaction(stats.total_hold_time),
+      refund_rate_bps: per_transaction(stats.refunded_count * 10_000),
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CampaignReceiverJson {
+  pub account_id: AccountId,
+  pub amount: U128,
+}
@@ -0,0 +1,36 @@
+use crate::*;
+use near_sdk::{ext_contract, Gas};
+
+pub const CLAIM_HOOK_GAS: Gas = 20_000_000_000_000;
+// Settling a claim also locks the referral commissions
+pub const CLAIM_CALLBACK_GAS: Gas = 20_000_000_000_000;
+
+// A claimed payment as delivered to the receiver, the sender paid the platform fee on top
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimedPayment {
+  pub created_at: u64,
+  pub transaction_id: String,
+  pub amount: U128,
+}
+
+// A claimed transaction whose payout is in flight, with the fee it releases to the platform
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingClaim {
+  pub sender_id: AccountId,
+  pub organization_id: Option<String>,
+  pub campaign_id: String,
+  pub created_at: u64,
+  pub transaction_id: String,
+  pub amount: U128,
+  pub fee: U128,
+  pub status: String, // of the transaction once claimed
+}
+
+// Interface a hook-enabled receiver contract implements, the attached deposit is the sum of
+// the payments. A failing call returns the deposit and the payments are locked again
+#[ext_contract(ext_claim_hook_receiver)]
+pub trait ClaimHookReceiver {
+  fn on_hurdle_payment_claimed(&mut self, payments: Vec<ClaimedPayment>);
+}
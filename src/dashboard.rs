@@ -0,0 +1,25 @@
+use crate::*;
+
+// Trailing epochs the dashboard keeps claimed value for, one slot per epoch
+pub const DASHBOARD_EPOCHS: u64 = 60;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct DashboardCounters {
+  pub active_senders: u64, // senders with at least one locked transaction
+  pub locked_transactions: u64,
+  pub claimed_transactions: u64,
+  pub refunded_transactions: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DashboardJson {
+  pub registered_accounts: u64,
+  pub active_senders: u64,
+  pub locked_transactions: u64,
+  pub claimed_transactions: u64,
+  pub refunded_transactions: u64,
+  pub value_locked: U128,
+  pub recent_epochs: u64,
+  pub value_claimed_recently: U128, // over the last `recent_epochs` epochs, current one included
+}
@@ -0,0 +1,155 @@
+use crate::*;
+
+pub const EVENT_STANDARD: &str = "hurdle_payment";
+pub const EVENT_VERSION: &str = "1.0.0";
+pub const EVENT_LOG_PREFIX: &str = "EVENT_JSON:";
+
+// NEP-297 envelope, shared with off-chain consumers so both sides agree on the format
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventLog {
+  pub standard: String,
+  pub version: String,
+  #[serde(flatten)]
+  pub event: HurdleEvent,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(
+  crate = "near_sdk::serde",
+  tag = "event",
+  content = "data",
+  rename_all = "snake_case"
+)]
+pub enum HurdleEvent {
+  AccountRegistered(Vec<AccountEventData>),
+  AccountClosed(Vec<AccountEventData>),
+  CampaignCreated(Vec<CampaignCreatedData>),
+  TransactionLocked(Vec<TransferTransactionJson>),
+  TransactionClaimed(Vec<TransactionClaimedData>),
+  TransactionRefunded(Vec<TransactionRefundedData>),
+  VelocityLimitExceeded(Vec<VelocityLimitExceededData>),
+  MilestoneReleased(Vec<MilestoneData>),
+  MilestoneReclaimed(Vec<MilestoneData>),
+  ApprovalRecorded(Vec<ApprovalRecordedData>),
+  ClaimPayoutFailed(Vec<ClaimPayoutFailedData>),
+  StreamWithdrawn(Vec<StreamWithdrawnData>),
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountEventData {
+  pub account_id: AccountId,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CampaignCreatedData {
+  pub campaign_id: String,
+  pub owner_id: AccountId,
+  pub refund_window: Option<u64>, // epochs
+}
+
+// Transactions are identified by receiver, creation epoch and id like in `refund_by_transaction_id`
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransactionClaimedData {
+  pub receiver_id: AccountId,
+  pub created_at: u64,
+  pub transaction_id: String,
+  pub amount: U128,   // paid out by this claim
+  pub status: String, // LOCK while a schedule is still vesting
+}
+
+// The payments are locked again, the receiver's next claim delivers them
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimPayoutFailedData {
+  pub receiver_id: AccountId,
+  pub payments: Vec<ClaimedPayment>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransactionRefundedData {
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub created_at: u64,
+  pub transaction_id: String,
+  pub amount: U128,
+}
+
+// Stream payouts go straight to the receiver and count towards its revenue
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StreamWithdrawnData {
+  pub stream_id: u64,
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub amount: U128,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MilestoneData {
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub created_at: u64,
+  pub transaction_id: String,
+  pub milestone_index: u64,
+  pub amount: U128,
+  pub status: String, // of the transaction afterwards
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ApprovalRecordedData {
+  pub receiver_id: AccountId,
+  pub created_at: u64,
+  pub transaction_id: String,
+  pub approver_id: AccountId,
+  pub approved: bool,
+  pub status: String, // of the approval afterwards
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VelocityLimitExceededData {
+  pub scope: String, // "sender" or "campaign"
+  pub limit: String, // "amount" or "count"
+  pub sender_id: AccountId,
+  pub campaign_id: String,
+  pub amount: U128,
+  pub window: u64, // seconds
+}
+
+impl HurdleEvent {
+  // Logs the event in the NEP-297 format so indexers can pick it up from receipts
+  pub(crate) fn emit(self) {
+    let log = EventLog {
+      standard: EVENT_STANDARD.to_string(),
+      version: EVENT_VERSION.to_string(),
+      event: self,
+    };
+    env::log(
+      format!(
+        "{}{}",
+        EVENT_LOG_PREFIX,
+        near_sdk::serde_json::to_string(&log).unwrap()
+      )
+      .as_bytes(),
+    );
+  }
+}
+
+impl EventLog {
+  // Parses a single log line, None when it is not an event of this contract's standard
+  pub fn parse(log: &str) -> Option<Self> {
+    let event_log: EventLog =
+      near_sdk::serde_json::from_str(log.strip_prefix(EVENT_LOG_PREFIX)?).ok()?;
+    if event_log.standard != EVENT_STANDARD {
+      return None;
+    }
+    Some(event_log)
+  }
+}
@@ -0,0 +1,670 @@
+// Randomized harness driving the escrow through register/send/claim/refund/milestone sequences,
+// together with organizations, approvals, platform fees, failed payouts, pruning and closing
+use crate::tests::{created_transfers, resolve_claims, TEST_ORGANIZATIONS};
+use crate::*;
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{testing_env, MockedBlockchain};
+use std::collections::HashMap;
+
+const RUNS: u64 = 32;
+const STEPS: u64 = 150;
+const START_EPOCH: u64 = 100;
+const NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+const INITIAL_BALANCE: Balance = 100 * NEAR;
+
+// xorshift64*, good enough to pick operations and reproducible from the seed
+struct Rng(u64);
+
+impl Rng {
+  fn next(&mut self) -> u64 {
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+  }
+
+  fn below(&mut self, bound: u64) -> u64 {
+    self.next() % bound
+  }
+}
+
+struct KnownTransaction {
+  sender_id: AccountId,
+  receiver_id: AccountId,
+  transaction_id: String,
+  create_epoch: u64,
+}
+
+// Last observed (status, claimed_amount) of every transaction
+type Snapshot = HashMap<(AccountId, u64, String), (String, Balance)>;
+
+// Totals of the settled transactions that pruning or closing removed from storage
+#[derive(Default)]
+struct Retired {
+  escrowed: Balance,
+  claimed: Balance,
+  refunded: Balance,
+}
+
+struct Harness {
+  context: VMContextBuilder,
+  contract: HurdlePayment,
+  epoch: u64,
+  transactions: Vec<KnownTransaction>,
+  snapshot: Snapshot,
+  retired: Retired,
+  // What the contract keeps on top of its liabilities: paid storage and one yocto deposits
+  retained: i128,
+}
+
+impl Harness {
+  fn new() -> Self {
+    // Drop the storage left behind by the previous run
+    env::take_blockchain_interface();
+    let mut context = VMContextBuilder::new();
+    context
+      .current_account_id(accounts(0))
+      .signer_account_id(accounts(0))
+      .predecessor_account_id(accounts(0))
+      .account_balance(INITIAL_BALANCE)
+      .epoch_height(START_EPOCH);
+    testing_env!(context.build());
+    let mut harness = Harness {
+      context,
+      contract: HurdlePayment::new(),
+      epoch: START_EPOCH,
+      transactions: Vec::new(),
+      snapshot: HashMap::new(),
+      retired: Retired::default(),
+      retained: 0,
+    };
+    // Payments of accounts(0) from 1 NEAR up wait for accounts(4) or accounts(5)
+    harness.call_as(accounts(0), NEAR);
+    harness
+      .contract
+      .create_campaign("fuzz".to_string(), None, None);
+    harness.call_as(accounts(0), 1);
+    harness.contract.set_campaign_approval_policy(
+      "fuzz".to_string(),
+      Some(ApprovalPolicyJson {
+        threshold: U128(NEAR),
+        required_approvals: 1,
+        approvers: vec![accounts(4).to_string(), accounts(5).to_string()],
+      }),
+    );
+    harness.call_as(accounts(0), 1);
+    harness.contract.set_retention_period(1);
+    for (index, organization_id) in TEST_ORGANIZATIONS.iter().enumerate() {
+      harness.call_as(organization_admin(index), NEAR);
+      harness
+        .contract
+        .create_organization(organization_id.to_string());
+    }
+    let reconciliation = harness.contract.get_reconciliation();
+    harness.retained = env::account_balance() as i128 - liabilities(&reconciliation) as i128;
+    harness
+  }
+
+  // The contract's balance carries over from the previous call, the VM adds the attached deposit
+  fn call_as(&mut self, predecessor_id: ValidAccountId, attached_deposit: Balance) {
+    testing_env!(self
+      .context
+      .storage_usage(env::storage_usage())
+      .account_balance(env::account_balance())
+      .predecessor_account_id(predecessor_id)
+      .attached_deposit(attached_deposit)
+      .epoch_height(self.epoch)
+      .build());
+  }
+
+  fn is_registered(&self, account_id: &AccountId) -> bool {
+    self.contract.accounts.get(account_id).is_some()
+  }
+
+  fn transaction_info(&self, known: &KnownTransaction) -> TransferTransactionJson {
+    self
+      .contract
+      .get_transactions_info(
+        known.receiver_id.clone(),
+        known.create_epoch,
+        known.create_epoch,
+      )
+      .into_iter()
+      .find(|transaction| transaction.transaction_id == known.transaction_id)
+      .unwrap()
+  }
+
+  fn stored_transactions(&self, account_id: &AccountId) -> Vec<(u64, String, TransferTransaction)> {
+    let account = match self.contract.accounts.get(account_id) {
+      Some(account) => account,
+      None => return Vec::new(),
+    };
+    account
+      .transactions
+      .iter()
+      .flat_map(|(epoch, transactions)| {
+        transactions
+          .to_vec()
+          .into_iter()
+          .map(move |(transaction_id, transaction)| (epoch, transaction_id, transaction))
+      })
+      .collect()
+  }
+
+  // Moves the transactions that are no longer stored into the retired totals
+  fn retire_missing(
+    &mut self,
+    account_id: &AccountId,
+    stored: Vec<(u64, String, TransferTransaction)>,
+  ) {
+    let remaining: Vec<(u64, String)> = self
+      .stored_transactions(account_id)
+      .into_iter()
+      .map(|(epoch, transaction_id, _)| (epoch, transaction_id))
+      .collect();
+    for (epoch, transaction_id, transaction) in stored {
+      if remaining.contains(&(epoch, transaction_id.clone())) {
+        continue;
+      }
+      assert_ne!(transaction.status, "LOCK", "Locked transaction was removed");
+      self.retired.escrowed += transaction.locked_balance;
+      self.retired.claimed += transaction.claimed_amount;
+      self.retired.refunded += refunded_amount(&transaction);
+      self.transactions.retain(|known| {
+        !(&known.receiver_id == account_id
+          && known.create_epoch == epoch
+          && known.transaction_id == transaction_id)
+      });
+    }
+  }
+
+  fn pick_transaction(&self, rng: &mut Rng) -> Option<&KnownTransaction> {
+    if self.transactions.is_empty() {
+      return None;
+    }
+    Some(&self.transactions[rng.below(self.transactions.len() as u64) as usize])
+  }
+
+  fn step(&mut self, rng: &mut Rng, step: u64) {
+    let before = self.contract.get_reconciliation();
+    let before_storage_usage = env::storage_usage();
+    if let Some(funding) = self.apply(rng, step, &before) {
+      self.retained += match funding {
+        Funding::Deposit(deposit) => deposit as i128,
+        Funding::Storage(deposit) => deposit as i128 + storage_cost_since(before_storage_usage),
+      };
+    }
+  }
+
+  // Runs one random operation, returns how the contract was paid for it unless it was skipped
+  fn apply(&mut self, rng: &mut Rng, step: u64, before: &ReconciliationJson) -> Option<Funding> {
+    let sender = accounts(rng.below(3) as usize);
+    let receiver = accounts(1 + rng.below(4) as usize);
+    match rng.below(11) {
+      0 => {
+        self.call_as(accounts(0), NEAR);
+        self
+          .contract
+          .register_new_account(receiver.to_string(), None);
+        Some(Funding::Storage(0))
+      }
+      1 | 2 => {
+        if !self.is_registered(&receiver.to_string()) {
+          return None;
+        }
+        let cash_hold_time = rng.below(3) as i64;
+        let refund_window = match rng.below(3) {
+          0 => None,
+          _ => Some(rng.below(cash_hold_time as u64 + 1)),
+        };
+        let y_amount = [0.1, 0.5, 1.0, 2.5][rng.below(4) as usize];
+        let schedule = random_schedule(rng);
+        let transaction_id = format!("fuzz{}", step);
+        self.call_as(sender.clone(), 10 * NEAR);
+        self.contract.send_to_contract(
+          receiver.to_string(),
+          y_amount,
+          cash_hold_time,
+          "fuzz".to_string(),
+          transaction_id.clone(),
+          refund_window,
+          schedule,
+        );
+        self.transactions.push(KnownTransaction {
+          sender_id: sender.to_string(),
+          receiver_id: receiver.to_string(),
+          transaction_id,
+          create_epoch: self.epoch,
+        });
+        Some(Funding::Storage(0))
+      }
+      3 => {
+        if !self.is_registered(&receiver.to_string()) {
+          return None;
+        }
+        self.call_as(accounts(5), NEAR);
+        let before_storage_usage = env::storage_usage();
+        self.contract.claim_and_withdraw(receiver.to_string());
+        // The caller gets back whatever the claim records did not use
+        let storage_cost = env::storage_byte_cost()
+          * Balance::from(env::storage_usage().saturating_sub(before_storage_usage));
+        assert_eq!(
+          transferred_to(&accounts(5).to_string()),
+          NEAR - storage_cost
+        );
+        let paid_out = transferred_to(&receiver.to_string());
+        // A bounced payout is back in the contract's balance by the time the callback runs
+        let delivered = rng.below(4) != 0;
+        let bounced = if delivered { 0 } else { paid_out };
+        self
+          .context
+          .account_balance(env::account_balance() + bounced)
+          .attached_deposit(0);
+        resolve_claims(&mut self.contract, &self.context, delivered);
+        let after = self.contract.get_reconciliation();
+        if delivered {
+          assert_eq!(
+            paid_out,
+            after.total_claimed.0 - before.total_claimed.0,
+            "claim paid out a different amount than it accounted"
+          );
+        } else {
+          assert_eq!(
+            (after.total_claimed.0, after.total_locked.0),
+            (before.total_claimed.0, before.total_locked.0),
+            "failed payout was not locked again"
+          );
+        }
+        Some(Funding::Deposit(storage_cost))
+      }
+      4 => {
+        let known = self.pick_transaction(rng)?;
+        let transaction = self.transaction_info(known);
+        // Refunds outside of the window are rejected by design unless the approval is overdue
+        let approval_overdue = transaction
+          .approval
+          .as_ref()
+          .is_some_and(|approval| approval.status != "APPROVED")
+          && self.epoch >= transaction.claimable_at;
+        if transaction.status != "LOCK"
+          || (self.epoch >= transaction.refundable_until && !approval_overdue)
+        {
+          return None;
+        }
+        let (sender_id, receiver_id, transaction_id, create_epoch) = (
+          known.sender_id.clone(),
+          known.receiver_id.clone(),
+          known.transaction_id.clone(),
+          known.create_epoch,
+        );
+        self.call_as(ValidAccountId::try_from(sender_id.clone()).unwrap(), 0);
+        self.contract.refund_by_transaction_id(
+          sender_id.clone(),
+          receiver_id,
+          transaction_id,
+          create_epoch,
+        );
+        self.assert_returned(before, &sender_id, "refund");
+        Some(Funding::Deposit(0))
+      }
+      5 => {
+        let known = self.pick_transaction(rng)?;
+        let transaction = self.transaction_info(known);
+        let milestones = match transaction.schedule {
+          PayoutSchedule::Milestones { milestones } if transaction.status == "LOCK" => milestones,
+          _ => return None,
+        };
+        let (sender_id, receiver_id, transaction_id, create_epoch) = (
+          known.sender_id.clone(),
+          known.receiver_id.clone(),
+          known.transaction_id.clone(),
+          known.create_epoch,
+        );
+        if rng.below(2) == 0 {
+          let index = rng.below(milestones.len() as u64);
+          let milestone = &milestones[index as usize];
+          if milestone.released_at.is_some() || milestone.reclaimed_at.is_some() {
+            return None;
+          }
+          self.call_as(accounts(5), 1);
+          self
+            .contract
+            .release_milestone(receiver_id, create_epoch, transaction_id, index);
+          return Some(Funding::Deposit(1));
+        }
+        // Only unreleased milestones past their deadline go back to the sender
+        if !milestones.iter().any(|milestone| {
+          milestone.released_at.is_none()
+            && milestone.reclaimed_at.is_none()
+            && self.epoch >= transaction.claimable_at.saturating_add(milestone.deadline)
+        }) {
+          return None;
+        }
+        self.call_as(ValidAccountId::try_from(sender_id.clone()).unwrap(), 0);
+        self
+          .contract
+          .reclaim_milestones(receiver_id, create_epoch, transaction_id);
+        self.assert_returned(before, &sender_id, "milestone reclaim");
+        Some(Funding::Deposit(0))
+      }
+      6 => {
+        if rng.below(2) == 0 {
+          self.epoch += 1 + rng.below(2);
+          return None;
+        }
+        if !self.is_registered(&receiver.to_string()) {
+          return None;
+        }
+        self.call_as(sender.clone(), 0);
+        self
+          .contract
+          .refund_by_epoch(sender.to_string(), receiver.to_string(), rng.below(3));
+        self.assert_returned(before, &sender.to_string(), "refund");
+        Some(Funding::Deposit(0))
+      }
+      7 => {
+        let index = rng.below(TEST_ORGANIZATIONS.len() as u64) as usize;
+        let organization_id = TEST_ORGANIZATIONS[index].to_string();
+        if rng.below(3) == 0 {
+          self.call_as(sender, (1 + rng.below(5)) as Balance * NEAR);
+          self.contract.deposit_organization_balance(organization_id);
+          return Some(Funding::Deposit(0));
+        }
+        if !self.is_registered(&receiver.to_string()) {
+          return None;
+        }
+        let amount = [NEAR / 10, NEAR / 2, NEAR, 5 * NEAR / 2][rng.below(4) as usize];
+        // Leaves room for the largest platform fee and the payment's storage
+        let prepaid_balance = self
+          .contract
+          .get_organization(organization_id.clone())
+          .prepaid_balance
+          .0;
+        if prepaid_balance < amount + amount / 10 + NEAR / 10 {
+          return None;
+        }
+        let admin = organization_admin(index);
+        let cash_hold_time = rng.below(3) as i64;
+        let refund_window = match rng.below(3) {
+          0 => None,
+          _ => Some(rng.below(cash_hold_time as u64 + 1)),
+        };
+        let schedule = random_schedule(rng);
+        let transaction_id = format!("fuzz{}", step);
+        self.call_as(admin.clone(), 1);
+        self.contract.send_from_organization(
+          organization_id,
+          receiver.to_string(),
+          U128(amount),
+          cash_hold_time,
+          "fuzz".to_string(),
+          transaction_id.clone(),
+          refund_window,
+          schedule,
+        );
+        self.transactions.push(KnownTransaction {
+          sender_id: admin.to_string(),
+          receiver_id: receiver.to_string(),
+          transaction_id,
+          create_epoch: self.epoch,
+        });
+        Some(Funding::Storage(1))
+      }
+      8 => {
+        let known = self.pick_transaction(rng)?;
+        let transaction = self.transaction_info(known);
+        let approval = match transaction.approval {
+          Some(approval) if transaction.status == "LOCK" && approval.status == "PENDING" => {
+            approval
+          }
+          _ => return None,
+        };
+        let approver_id = &approval.approvers[rng.below(approval.approvers.len() as u64) as usize];
+        if approval.approved_by.contains(approver_id) || approval.rejected_by.contains(approver_id)
+        {
+          return None;
+        }
+        let (sender_id, receiver_id, transaction_id, create_epoch) = (
+          known.sender_id.clone(),
+          known.receiver_id.clone(),
+          known.transaction_id.clone(),
+          known.create_epoch,
+        );
+        self.call_as(ValidAccountId::try_from(approver_id.clone()).unwrap(), 1);
+        if rng.below(2) == 0 {
+          self
+            .contract
+            .approve_transaction(receiver_id, create_epoch, transaction_id);
+        } else {
+          self
+            .contract
+            .reject_transaction(receiver_id, create_epoch, transaction_id);
+          self.assert_returned(before, &sender_id, "rejection");
+        }
+        Some(Funding::Deposit(1))
+      }
+      9 => {
+        let platform_fee_bps = [0, 100, MAX_PLATFORM_FEE_BPS][rng.below(3) as usize];
+        self.call_as(accounts(0), 1);
+        self.contract.set_platform_fee(platform_fee_bps);
+        Some(Funding::Deposit(1))
+      }
+      _ => {
+        let receiver_id = receiver.to_string();
+        let account = self.contract.accounts.get(&receiver_id)?;
+        let stored = self.stored_transactions(&receiver_id);
+        if rng.below(2) == 0 {
+          self.call_as(sender, 0);
+          self.contract.prune_settled_epochs(receiver_id.clone());
+          self.retire_missing(&receiver_id, stored);
+          return Some(Funding::Storage(0));
+        }
+        // Only an account with nothing locked can close
+        if account.locked_balance > 0 {
+          return None;
+        }
+        self.call_as(receiver, 1);
+        self.contract.close_account();
+        self.retire_missing(&receiver_id, stored);
+        Some(Funding::Storage(1))
+      }
+    }
+  }
+
+  // Refunds hand back the payment and its held fee, by transfer or into the organization
+  fn assert_returned(&self, before: &ReconciliationJson, sender_id: &AccountId, operation: &str) {
+    let after = self.contract.get_reconciliation();
+    let returned = after.total_refunded.0 - before.total_refunded.0 + before.total_held_fees.0
+      - after.total_held_fees.0;
+    assert_eq!(
+      transferred_to(sender_id) + after.total_prepaid.0 - before.total_prepaid.0,
+      returned,
+      "{} paid out a different amount than it accounted",
+      operation
+    );
+  }
+
+  fn check_invariants(&mut self) {
+    let reconciliation = self.contract.get_reconciliation();
+    let mut total_locked = 0;
+    let mut total_escrowed = 0;
+    let mut total_claimed = 0;
+    let mut total_refunded = 0;
+    let mut total_held_fees = 0;
+    let mut snapshot = HashMap::new();
+    for index in 1..5 {
+      let account_id = accounts(index).to_string();
+      let account = match self.contract.accounts.get(&account_id) {
+        Some(account) => account,
+        None => continue,
+      };
+      let mut outstanding = 0;
+      for (epoch, transactions) in account.transactions.iter() {
+        for (transaction_id, transaction) in transactions.iter() {
+          assert!(transaction.claimed_amount <= transaction.locked_balance);
+          let reclaimed = reclaimed_amount(&transaction);
+          match transaction.status.as_str() {
+            "LOCK" => outstanding += transaction.outstanding_amount(),
+            "CLAIM" => assert_eq!(
+              transaction.claimed_amount + reclaimed,
+              transaction.locked_balance
+            ),
+            "REFUND" => assert_eq!(transaction.claimed_amount, 0),
+            status => panic!("Unexpected status {}", status),
+          }
+          total_refunded += refunded_amount(&transaction);
+          total_held_fees += transaction.held_fee;
+          total_escrowed += transaction.locked_balance;
+          total_claimed += transaction.claimed_amount;
+
+          let key = (account_id.clone(), epoch, transaction_id);
+          if let Some((status, claimed_amount)) = self.snapshot.get(&key) {
+            assert!(
+              status == "LOCK" || status == &transaction.status,
+              "Transaction went from {} to {}",
+              status,
+              transaction.status
+            );
+            assert!(*claimed_amount <= transaction.claimed_amount);
+          }
+          snapshot.insert(key, (transaction.status, transaction.claimed_amount));
+        }
+      }
+      assert_eq!(outstanding, account.locked_balance);
+      total_locked += account.locked_balance;
+    }
+    assert_eq!(snapshot.len(), self.transactions.len());
+    self.snapshot = snapshot;
+    total_escrowed += self.retired.escrowed;
+    total_claimed += self.retired.claimed;
+    total_refunded += self.retired.refunded;
+    let total_prepaid: Balance = TEST_ORGANIZATIONS
+      .iter()
+      .map(|organization_id| {
+        self
+          .contract
+          .get_organization(organization_id.to_string())
+          .prepaid_balance
+          .0
+      })
+      .sum();
+
+    assert_eq!(reconciliation.total_locked.0, total_locked);
+    assert_eq!(reconciliation.total_held_fees.0, total_held_fees);
+    assert_eq!(reconciliation.total_prepaid.0, total_prepaid);
+    assert_eq!(reconciliation.total_escrowed.0, total_escrowed);
+    assert_eq!(reconciliation.total_claimed.0, total_claimed);
+    assert_eq!(reconciliation.total_refunded.0, total_refunded);
+    assert_eq!(
+      total_escrowed,
+      total_locked + total_claimed + total_refunded,
+      "escrowed balance is not conserved"
+    );
+    assert_eq!(
+      reconciliation.account_balance.0 as i128 - liabilities(&reconciliation) as i128,
+      self.retained,
+      "contract balance does not match the accounting totals"
+    );
+  }
+}
+
+// How the contract's balance should move past its liabilities for one operation
+enum Funding {
+  // The call keeps this part of the deposit, storage changes are nobody's to pay
+  Deposit(Balance),
+  // The call keeps this part of the deposit and pays for or refunds its storage changes exactly
+  Storage(Balance),
+}
+
+fn liabilities(reconciliation: &ReconciliationJson) -> Balance {
+  reconciliation.total_locked.0
+    + reconciliation.total_fees.0
+    + reconciliation.total_prepaid.0
+    + reconciliation.total_streaming.0
+    + reconciliation.total_held_fees.0
+}
+
+fn storage_cost_since(before_storage_usage: u64) -> i128 {
+  env::storage_byte_cost() as i128 * (env::storage_usage() as i128 - before_storage_usage as i128)
+}
+
+// Each test organization is created and run by the account with the same index
+fn organization_admin(index: usize) -> ValidAccountId {
+  accounts(index)
+}
+
+// Reclaimed milestones count as refunded while the rest of the transaction settles
+fn reclaimed_amount(transaction: &TransferTransaction) -> Balance {
+  transaction
+    .reclaims()
+    .iter()
+    .map(|(_, amount)| amount)
+    .sum()
+}
+
+fn refunded_amount(transaction: &TransferTransaction) -> Balance {
+  if transaction.status == "REFUND" {
+    transaction.locked_balance
+  } else {
+    reclaimed_amount(transaction)
+  }
+}
+
+// Sum of the transfers the last call made to `account_id`
+fn transferred_to(account_id: &AccountId) -> Balance {
+  created_transfers()
+    .into_iter()
+    .filter(|(receiver_id, _)| receiver_id == account_id)
+    .map(|(_, amount)| amount)
+    .sum()
+}
+
+// Lump payments as often as each of the other schedules, offsets in days
+fn random_schedule(rng: &mut Rng) -> Option<PayoutSchedule> {
+  let first_bps = 1 + rng.below(9_999) as u16;
+  match rng.below(4) {
+    0 => None,
+    1 => Some(PayoutSchedule::Linear {
+      cliff: rng.below(2),
+      duration: 1 + rng.below(3),
+    }),
+    2 => Some(PayoutSchedule::Tranches {
+      tranches: vec![
+        Tranche {
+          release_after: 0,
+          share_bps: first_bps,
+        },
+        Tranche {
+          release_after: 1 + rng.below(2),
+          share_bps: 10_000 - first_bps,
+        },
+      ],
+    }),
+    _ => Some(PayoutSchedule::Milestones {
+      milestones: [first_bps, 10_000 - first_bps]
+        .iter()
+        .map(|share_bps| Milestone {
+          share_bps: *share_bps,
+          approver_id: accounts(5).to_string(),
+          oracle: false,
+          deadline: rng.below(3),
+          released_at: None,
+          reclaimed_at: None,
+        })
+        .collect(),
+    }),
+  }
+}
+
+#[test]
+fn test_random_escrow_sequences() {
+  for seed in 1..=RUNS {
+    let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    let mut harness = Harness::new();
+    for step in 0..STEPS {
+      harness.step(&mut rng, step);
+      harness.check_invariants();
+    }
+  }
+}
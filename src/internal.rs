@@ -1,4 +1,5 @@
 use crate::*;
+use std::collections::BTreeMap;
 
 #[near_bindgen]
 impl HurdlePayment {
@@ -7,88 +8,528 @@ impl HurdlePayment {
       locked_balance: 0,
       total_revenue: 0,
       last_unlock_at: env::epoch_height(),
+      kyc_tier: 0,
+      claim_day: today(),
+      claimed_today: 0,
+      claim_hook_enabled: false,
       transactions: UnorderedMap::new(StorageKey::AccountTransactionByDate {
         account_hash: env::sha256(account_id.as_bytes()),
       }),
     };
     self.accounts.insert(&account_id, &account);
+    self.account_ids.insert(&account_id);
+    HurdleEvent::AccountRegistered(vec![AccountEventData { account_id }]).emit();
   }
 
+  // Returns the platform fee the sender owes on top of the amount, None if the payment was rejected
+  #[allow(clippy::too_many_arguments)]
   pub(crate) fn internal_create_transfer_transaction(
     &mut self,
+    sender_id: AccountId,
     receiver_id: AccountId,
     amount: Balance,
     cash_hold_time: i64,
+    refund_window: Option<u64>,
+    schedule: PayoutSchedule,
     campaign_id: String,
     transaction_id: String,
-  ) {
-    let account = self.accounts.get(&receiver_id);
-    assert!(account.is_some(), "Account not found");
-    let mut account = account.unwrap();
+    organization_id: Option<String>,
+  ) -> Option<Balance> {
+    self.internal_screen_sender(&sender_id);
+    self.internal_screen_receiver(&receiver_id);
+    let claimable_at = env::epoch_height() + cash_hold_time as u64;
+    schedule.assert_valid(claimable_at);
+    let campaign = self.internal_sender_campaign(&sender_id, &campaign_id, &organization_id);
+    // Only the campaign's own payments count towards its limit, anyone can tag a campaign id
+    if !self.internal_check_velocity(&sender_id, &campaign_id, campaign.is_some(), amount) {
+      return None;
+    }
+    // Refunds stay open for the whole hold time unless the call or the campaign sets a shorter window
+    let refund_window = refund_window.or_else(|| {
+      campaign
+        .as_ref()
+        .and_then(|campaign| campaign.refund_window)
+    });
+    let refundable_until = match refund_window {
+      Some(refund_window) => std::cmp::min(
+        env::epoch_height().saturating_add(refund_window),
+        claimable_at,
+      ),
+      None => claimable_at,
+    };
+    let approval = campaign
+      .and_then(|campaign| campaign.approval_policy)
+      .filter(|policy| amount >= policy.threshold)
+      .map(|policy| TransactionApproval {
+        required_approvals: policy.required_approvals,
+        approvers: policy.approvers,
+        approved_by: Vec::new(),
+        rejected_by: Vec::new(),
+        status: "PENDING".to_string(),
+      });
+    // Conditional payments are only taken back milestone by milestone, once their deadline passes
+    let (refundable_until, transaction_type) = match schedule {
+      PayoutSchedule::Milestones { .. } => (env::epoch_height(), "CONDITIONAL"),
+      _ => (refundable_until, "PAYMENT"),
+    };
+    let platform_fee = self.internal_platform_fee(amount);
     let trans = TransferTransaction {
-      sender_id: env::predecessor_account_id(),
-      receiver_id: receiver_id.clone(),
-      campaign_id: campaign_id,
+      sender_id,
+      receiver_id,
+      campaign_id,
       locked_balance: amount,
       created_at: env::epoch_height(),
-      claimable_at: env::epoch_height() + cash_hold_time as u64,
+      claimable_at,
+      refundable_until,
+      schedule,
+      claimed_amount: 0,
+      claims: Vec::new(),
+      refunded_at: None,
       status: "LOCK".to_string(),
+      transaction_type: transaction_type.to_string(),
+      approval,
+      organization_id,
+      platform_fee_bps: self.fee_policy.platform_fee_bps,
+      held_fee: platform_fee,
     };
+    self.internal_lock_transaction(transaction_id, trans);
+    self.totals.total_held_fees += platform_fee;
+    Some(platform_fee)
+  }
+
+  pub(crate) fn internal_platform_fee(&self, amount: Balance) -> Balance {
+    amount * self.fee_policy.platform_fee_bps as u128 / 10_000
+  }
+
+  // Files the transaction in the receiver's bucket for the current epoch and books it as locked
+  pub(crate) fn internal_lock_transaction(
+    &mut self,
+    transaction_id: String,
+    trans: TransferTransaction,
+  ) {
+    let receiver_id = trans.receiver_id.clone();
+    let amount = trans.locked_balance;
+    let account = self.accounts.get(&receiver_id);
+    assert!(account.is_some(), "Account not found");
+    let mut account = account.unwrap();
     let today_epoch = env::epoch_height();
     let transactions = account.transactions.get(&today_epoch);
-    if transactions.is_none() {
+    if let Some(mut transactions) = transactions {
+      let check_transaction = transactions.get(&transaction_id);
+      assert!(check_transaction.is_none(), "Transaction ID Duplicated");
+      transactions.insert(&transaction_id, &trans);
+      account.transactions.insert(&today_epoch, &transactions);
+    } else {
+      // Every receiver needs its own prefix for the epoch bucket
       let mut map = UnorderedMap::new(StorageKey::AccountTransaction {
-        account_hash: env::sha256(env::epoch_height().to_string().as_bytes()),
+        account_hash: env::sha256(format!("{}:{}", receiver_id, today_epoch).as_bytes()),
       });
       map.insert(&transaction_id, &trans);
       account.transactions.insert(&today_epoch, &map);
-    } else {
-      let mut transactions = transactions.unwrap();
-      let check_transaction = transactions.get(&transaction_id);
-      assert_eq!(
-        check_transaction.is_none(),
-        true,
-        "Transaction ID Duplicated"
-      );
-      transactions.insert(&transaction_id, &trans);
-      account.transactions.insert(&today_epoch, &transactions);
     }
+    self.internal_record_campaign_lock(&trans);
+    self.internal_record_dashboard_lock(&trans.sender_id);
+    HurdleEvent::TransactionLocked(vec![TransferTransactionJson::from(transaction_id, trans)])
+      .emit();
 
     account.locked_balance += amount;
     account.total_revenue += amount;
+    self.totals.total_escrowed += amount;
+    self.totals.total_locked += amount;
+    if let Some(limit) = self.tier_limits.get(&account.kyc_tier) {
+      if let Some(max_locked_balance) = limit.max_locked_balance {
+        assert!(
+          account.locked_balance <= max_locked_balance,
+          "KYC tier {} allows at most {} yoctoNEAR locked for {}",
+          account.kyc_tier,
+          max_locked_balance,
+          receiver_id
+        );
+      }
+      if let Some(max_lifetime_revenue) = limit.max_lifetime_revenue {
+        assert!(
+          account.total_revenue <= max_lifetime_revenue,
+          "KYC tier {} allows at most {} yoctoNEAR lifetime revenue for {}",
+          account.kyc_tier,
+          max_lifetime_revenue,
+          receiver_id
+        );
+      }
+    }
     self.accounts.insert(&receiver_id, &account);
   }
 
   pub(crate) fn internal_unlock_locked_balance(&mut self, account_id: AccountId) {
+    // Locked funds of blocked receivers stay frozen in the contract
+    self.internal_screen_receiver(&account_id);
     let account = self.accounts.get(&account_id);
     assert!(account.is_some(), "Account not found");
     let mut account = account.unwrap();
     let mut last_unlock_at = account.last_unlock_at;
+    // Earliest epoch that still holds a locked transaction, where the next claim has to start
+    let mut next_unlock_at = None;
     let mut transer_amount = 0;
+    let mut capped_amount = 0;
+    let mut claims = Vec::new();
+    let mut payments = Vec::new();
+    if account.claim_day != today() {
+      account.claim_day = today();
+      account.claimed_today = 0;
+    }
+    // Whatever the KYC tier's daily cap leaves, the rest stays locked for a later day
+    let allowance = self
+      .tier_limits
+      .get(&account.kyc_tier)
+      .and_then(|limit| limit.max_claim_per_day)
+      .map_or(Balance::MAX, |max_claim_per_day| {
+        max_claim_per_day.saturating_sub(account.claimed_today)
+      });
     while last_unlock_at <= env::epoch_height() {
       let transactions = account.transactions.get(&last_unlock_at);
-      if transactions.is_some() {
-        let mut transactions = transactions.unwrap();
+      if let Some(mut transactions) = transactions {
         for transaction in transactions.to_vec() {
           let (transaction_id, mut transaction) = transaction;
-          if env::epoch_height() >= transaction.claimable_at
-            && transaction.status == "LOCK".to_string()
-          {
-            transaction.status = "CLAIM".to_string();
+          if transaction.status != "LOCK" {
+            continue;
+          }
+          // Payments waiting for approval stay locked without holding back the ones after them
+          let vested_amount = if transaction.is_approved() {
+            transaction.vested_amount(env::epoch_height()) - transaction.claimed_amount
+          } else {
+            0
+          };
+          let claimable_amount = std::cmp::min(vested_amount, allowance - transer_amount);
+          capped_amount += vested_amount - claimable_amount;
+          if claimable_amount > 0 {
+            // Reserved right away so a second claim can't take it while the payout is in flight.
+            // The sender paid the fee, the receiver gets the whole amount
+            let fee = transaction.record_claim(env::epoch_height(), claimable_amount);
+            if transaction.outstanding_amount() == 0 {
+              transaction.status = "CLAIM".to_string();
+            }
             transactions.insert(&transaction_id, &transaction);
             account.locked_balance = account
               .locked_balance
-              .checked_sub(transaction.locked_balance)
+              .checked_sub(claimable_amount)
               .unwrap();
-            transer_amount += transaction.locked_balance;
+            transer_amount += claimable_amount;
+            payments.push(ClaimedPayment {
+              created_at: last_unlock_at,
+              transaction_id: transaction_id.clone(),
+              amount: U128(claimable_amount),
+            });
+            claims.push(PendingClaim {
+              sender_id: transaction.sender_id.clone(),
+              organization_id: transaction.organization_id.clone(),
+              campaign_id: transaction.campaign_id.clone(),
+              created_at: last_unlock_at,
+              transaction_id,
+              amount: U128(claimable_amount),
+              fee: U128(fee),
+              status: transaction.status.clone(),
+            });
+          }
+          if transaction.status == "LOCK" && next_unlock_at.is_none() {
+            next_unlock_at = Some(last_unlock_at);
           }
         }
       }
       last_unlock_at += 1;
     }
-    Promise::new(account_id.clone()).transfer(transer_amount);
-    account.last_unlock_at = env::epoch_height();
+    assert!(
+      capped_amount == 0 || transer_amount > 0,
+      "KYC tier {} has {} yoctoNEAR of daily claim allowance left, {} yoctoNEAR stays locked",
+      account.kyc_tier,
+      allowance,
+      capped_amount
+    );
+    if capped_amount > 0 {
+      env::log(
+        format!(
+          "Claim capped by the daily allowance of KYC tier {}, {} yoctoNEAR stays locked",
+          account.kyc_tier, capped_amount
+        )
+        .as_bytes(),
+      );
+    }
+    account.claimed_today += transer_amount;
+    self.totals.total_locked -= transer_amount;
+    let payout = transer_amount;
+    let claim_hook_enabled = account.claim_hook_enabled;
+    account.last_unlock_at = next_unlock_at.unwrap_or_else(env::epoch_height);
+    self.accounts.insert(&account_id, &account);
+    if claims.is_empty() {
+      return;
+    }
+    // The claim is settled or locked again once the payout's outcome is known
+    let payout_promise = if claim_hook_enabled && payout > 0 {
+      ext_claim_hook_receiver::on_hurdle_payment_claimed(
+        payments,
+        &account_id,
+        payout,
+        CLAIM_HOOK_GAS,
+      )
+    } else {
+      Promise::new(account_id.clone()).transfer(payout)
+    };
+    payout_promise.then(ext_self::on_claim_payout_result(
+      account_id,
+      env::epoch_height(),
+      claims,
+      &env::current_account_id(),
+      0,
+      CLAIM_CALLBACK_GAS,
+    ));
+  }
+
+  // The payout reached the receiver, only now does the claim count as claimed
+  pub(crate) fn internal_settle_claims(
+    &mut self,
+    account_id: &AccountId,
+    claims: Vec<PendingClaim>,
+  ) {
+    let mut transer_amount = 0;
+    let mut fee_amount = 0;
+    let mut claimed = Vec::new();
+    for claim in claims {
+      transer_amount += claim.amount.0;
+      fee_amount += claim.fee.0;
+      if claim.status == "CLAIM" {
+        self.internal_record_dashboard_settle(&claim.sender_id, "CLAIM");
+      }
+      self.internal_record_campaign_claim(&claim.campaign_id, claim.amount.0);
+      claimed.push(TransactionClaimedData {
+        receiver_id: account_id.clone(),
+        created_at: claim.created_at,
+        transaction_id: claim.transaction_id,
+        amount: claim.amount,
+        status: claim.status,
+      });
+    }
+    self.totals.total_claimed += transer_amount;
+    self.totals.total_held_fees -= fee_amount;
+    self.totals.total_fees += fee_amount;
+    if transer_amount > 0 {
+      self.internal_record_dashboard_claim(transer_amount);
+    }
+    if !claimed.is_empty() {
+      HurdleEvent::TransactionClaimed(claimed).emit();
+    }
+    if fee_amount > 0 {
+      self.internal_pay_referral_commissions(account_id, fee_amount);
+    }
+  }
+
+  // The payout of a failed transfer or hook call came back to the contract, the claimed
+  // transactions are locked again so the receiver's next claim pays them out
+  pub(crate) fn internal_revert_claims(
+    &mut self,
+    account_id: AccountId,
+    claimed_at: u64,
+    claims: Vec<PendingClaim>,
+  ) {
+    let mut account = match self.accounts.get(&account_id) {
+      Some(account) => account,
+      // Closing the account cleared its transactions, there is nothing left to lock them in
+      None => {
+        self.internal_refund_claims(&account_id, claims);
+        return;
+      }
+    };
+    let mut reverted = Vec::new();
+    let mut delivered = Vec::new();
+    let mut reverted_amount = 0;
+    for claim in claims {
+      let mut transactions = account.transactions.get(&claim.created_at);
+      let transaction = transactions
+        .as_ref()
+        .and_then(|transactions| transactions.get(&claim.transaction_id))
+        .filter(|transaction| transaction.status != "REFUND");
+      match (transactions.as_mut(), transaction) {
+        (Some(transactions), Some(mut transaction)) => {
+          transaction.revert_claim(claimed_at, claim.amount.0, claim.fee.0);
+          transaction.status = "LOCK".to_string();
+          transactions.insert(&claim.transaction_id, &transaction);
+          account.last_unlock_at = std::cmp::min(account.last_unlock_at, claim.created_at);
+          reverted_amount += claim.amount.0;
+          reverted.push(ClaimedPayment {
+            created_at: claim.created_at,
+            transaction_id: claim.transaction_id,
+            amount: claim.amount,
+          });
+        }
+        // A transaction that was refunded meanwhile can't be locked again, the claim stands
+        _ => delivered.push(claim),
+      }
+    }
+    account.locked_balance += reverted_amount;
+    if account.claim_day == today() {
+      account.claimed_today = account.claimed_today.saturating_sub(reverted_amount);
+    }
+    self.totals.total_locked += reverted_amount;
     self.accounts.insert(&account_id, &account);
+    if !reverted.is_empty() {
+      HurdleEvent::ClaimPayoutFailed(vec![ClaimPayoutFailedData {
+        receiver_id: account_id.clone(),
+        payments: reverted,
+      }])
+      .emit();
+    }
+    if !delivered.is_empty() {
+      let payout: Balance = delivered.iter().map(|claim| claim.amount.0).sum();
+      Promise::new(account_id.clone()).transfer(payout);
+      self.internal_settle_claims(&account_id, delivered);
+    }
+  }
+
+  // The receiver is gone, so the payments were never claimed and go back to their senders with
+  // the fees they paid
+  fn internal_refund_claims(&mut self, receiver_id: &AccountId, claims: Vec<PendingClaim>) {
+    let mut refunded = Vec::new();
+    for claim in claims {
+      let (amount, fee) = (claim.amount.0, claim.fee.0);
+      self.totals.total_refunded += amount;
+      self.totals.total_held_fees -= fee;
+      self.internal_record_campaign_refund(&claim.campaign_id, receiver_id, amount, false);
+      if claim.status == "CLAIM" {
+        self.internal_record_dashboard_settle(&claim.sender_id, "REFUND");
+      }
+      self.internal_return_to_sender(&claim.sender_id, &claim.organization_id, amount + fee);
+      refunded.push(TransactionRefundedData {
+        sender_id: claim.sender_id,
+        receiver_id: receiver_id.clone(),
+        created_at: claim.created_at,
+        transaction_id: claim.transaction_id,
+        amount: claim.amount,
+      });
+    }
+    if !refunded.is_empty() {
+      HurdleEvent::TransactionRefunded(refunded).emit();
+    }
+  }
+
+  // Walks up the referrer chain and locks each level's share of the fee for its referrer
+  fn internal_pay_referral_commissions(&mut self, account_id: &AccountId, fee_amount: Balance) {
+    let mut referee_id = account_id.clone();
+    let mut visited = vec![account_id.clone()];
+    for level_bps in self.fee_policy.referral_level_bps.clone() {
+      let referrer_id = match self.referrers.get(&referee_id) {
+        Some(referrer_id) => referrer_id,
+        None => break,
+      };
+      if visited.contains(&referrer_id) {
+        break;
+      }
+      let commission = fee_amount * level_bps as u128 / 10_000;
+      // The commission pays for its own transaction's storage, what is left is locked
+      let storage_cost = env::storage_byte_cost() * Balance::from(TRANSACTION_STORAGE_ESTIMATE);
+      let locked_commission = commission.saturating_sub(storage_cost);
+      // A referrer that could not take the commission forfeits it to the platform instead of
+      // failing the referee's claim
+      if locked_commission > 0 && self.internal_can_receive(&referrer_id, locked_commission) {
+        self.next_referral_id += 1;
+        let trans = TransferTransaction {
+          sender_id: env::current_account_id(),
+          receiver_id: referrer_id.clone(),
+          campaign_id: REFERRAL_CAMPAIGN_ID.to_string(),
+          locked_balance: locked_commission,
+          created_at: env::epoch_height(),
+          claimable_at: env::epoch_height() + self.fee_policy.referral_cash_hold_time,
+          refundable_until: env::epoch_height(),
+          schedule: PayoutSchedule::Lump,
+          claimed_amount: 0,
+          claims: Vec::new(),
+          refunded_at: None,
+          status: "LOCK".to_string(),
+          transaction_type: "REFERRAL".to_string(),
+          approval: None,
+          organization_id: None,
+          platform_fee_bps: 0,
+          held_fee: 0,
+        };
+        self.totals.total_fees -= commission;
+        self.internal_lock_transaction(format!("referral-{}", self.next_referral_id), trans);
+      }
+      visited.push(referrer_id.clone());
+      referee_id = referrer_id;
+    }
+  }
+
+  // Whether locking `amount` for the receiver would pass screening and its KYC limits
+  pub(crate) fn internal_can_receive(&self, receiver_id: &AccountId, amount: Balance) -> bool {
+    let account = match self.accounts.get(receiver_id) {
+      Some(account) => account,
+      None => return false,
+    };
+    if self.blocked_receivers.contains(receiver_id) || !self.internal_is_allowlisted(receiver_id) {
+      return false;
+    }
+    match self.tier_limits.get(&account.kyc_tier) {
+      Some(limit) => {
+        limit
+          .max_locked_balance
+          .is_none_or(|max| account.locked_balance + amount <= max)
+          && limit
+            .max_lifetime_revenue
+            .is_none_or(|max| account.total_revenue + amount <= max)
+      }
+      None => true,
+    }
+  }
+
+  pub(crate) fn internal_can_send(&self, sender_id: &AccountId) -> bool {
+    !self.blocked_senders.contains(sender_id) && self.internal_is_allowlisted(sender_id)
+  }
+
+  pub(crate) fn internal_register_referrer(
+    &mut self,
+    account_id: &AccountId,
+    referrer_id: AccountId,
+  ) {
+    assert!(
+      self.accounts.get(&referrer_id).is_some(),
+      "Referrer not found"
+    );
+    assert_ne!(account_id, &referrer_id, "An account cannot refer itself");
+    self.referrers.insert(account_id, &referrer_id);
+    let mut referrals = self.referrals.get(&referrer_id).unwrap_or_default();
+    referrals.push(account_id.clone());
+    self.referrals.insert(&referrer_id, &referrals);
+  }
+
+  // Closed accounts leave their referrer's tree, their own referrals keep pointing at them
+  fn internal_remove_referrer(&mut self, account_id: &AccountId) {
+    if let Some(referrer_id) = self.referrers.remove(account_id) {
+      let mut referrals = self.referrals.get(&referrer_id).unwrap_or_default();
+      referrals.retain(|referral_id| referral_id != account_id);
+      if referrals.is_empty() {
+        self.referrals.remove(&referrer_id);
+      } else {
+        self.referrals.insert(&referrer_id, &referrals);
+      }
+    }
+  }
+
+  pub(crate) fn internal_referral_tree(
+    &self,
+    account_id: AccountId,
+    depth: u64,
+  ) -> ReferralNodeJson {
+    let referrals = if depth == 0 {
+      Vec::new()
+    } else {
+      self
+        .referrals
+        .get(&account_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|referral_id| self.internal_referral_tree(referral_id, depth - 1))
+        .collect()
+    };
+    ReferralNodeJson {
+      account_id,
+      referrals,
+    }
   }
 
   pub(crate) fn internal_refund_by_transaction_id(
@@ -98,31 +539,167 @@ impl HurdlePayment {
     transaction_id: String,
     create_epoch: u64,
   ) {
+    self.internal_screen_sender(&sender_id);
     let account = self.accounts.get(&receiver_id);
     assert!(account.is_some(), "Account not found");
-    let mut account = account.unwrap();
-    let transactions = account.transactions.get(&create_epoch);
-    if transactions.is_some() {
-      let mut transactions = transactions.unwrap();
-      let transaction = transactions.get(&transaction_id);
-      if transaction.is_some() {
-        let mut transaction = transaction.unwrap();
-        if env::epoch_height() < transaction.claimable_at
-          && transaction.status == "LOCK".to_string()
-        {
-          transaction.status = "REFUND".to_string();
-          account.locked_balance = account
-            .locked_balance
-            .checked_sub(transaction.locked_balance)
-            .unwrap();
-          account.total_revenue = account
-            .total_revenue
-            .checked_sub(transaction.locked_balance)
-            .unwrap();
-          transactions.insert(&transaction_id, &transaction);
-          Promise::new(sender_id.clone()).transfer(transaction.locked_balance);
-          self.accounts.insert(&receiver_id, &account);
+    let transaction = account
+      .unwrap()
+      .transactions
+      .get(&create_epoch)
+      .and_then(|transactions| transactions.get(&transaction_id));
+    if let Some(transaction) = transaction {
+      assert_eq!(
+        transaction.sender_id, sender_id,
+        "Transaction was sent by another account"
+      );
+      match &transaction.organization_id {
+        Some(organization_id) => {
+          self.internal_assert_organization_role(organization_id, &["ADMIN", "REFUNDER"]);
         }
+        // Otherwise anyone could pull back a payment whose approval is overdue
+        None => assert_eq!(
+          env::predecessor_account_id(),
+          transaction.sender_id,
+          "Only the sender can refund the transaction"
+        ),
+      }
+      if transaction.status == "LOCK" {
+        // A payment still waiting for approval after its hold time can always be taken back
+        let approval_overdue =
+          !transaction.is_approved() && env::epoch_height() >= transaction.claimable_at;
+        assert!(
+          env::epoch_height() < transaction.refundable_until || approval_overdue,
+          "Refund window has closed"
+        );
+        self.internal_refund_transaction(receiver_id, create_epoch, transaction_id, transaction);
+      }
+    }
+  }
+
+  pub(crate) fn internal_record_approval(
+    &mut self,
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+    approved: bool,
+  ) {
+    let mut transaction =
+      self.internal_get_transaction(&receiver_id, create_epoch, &transaction_id);
+    assert_eq!(transaction.status, "LOCK", "Transaction is already settled");
+    let approver_id = env::predecessor_account_id();
+    let approval = transaction.approval.as_mut();
+    assert!(approval.is_some(), "Transaction does not need approval");
+    let approval = approval.unwrap();
+    assert_eq!(approval.status, "PENDING", "Approval is already decided");
+    assert!(
+      approval.approvers.contains(&approver_id),
+      "Only the campaign's approvers can decide on the transaction"
+    );
+    if let Some(organization_id) = &transaction.organization_id {
+      assert!(
+        self
+          .organizations
+          .get(organization_id)
+          .is_some_and(|organization| organization.role(&approver_id).is_some()),
+        "Approvers must be members of the campaign's organization"
+      );
+    }
+    assert!(
+      !approval.approved_by.contains(&approver_id) && !approval.rejected_by.contains(&approver_id),
+      "Approver already decided"
+    );
+    if approved {
+      approval.approved_by.push(approver_id.clone());
+    } else {
+      approval.rejected_by.push(approver_id.clone());
+    }
+    let required_approvals = approval.required_approvals as usize;
+    if approval.approved_by.len() >= required_approvals {
+      approval.status = "APPROVED".to_string();
+    } else if approval.approvers.len() - approval.rejected_by.len() < required_approvals {
+      approval.status = "REJECTED".to_string();
+    }
+    let status = approval.status.clone();
+    HurdleEvent::ApprovalRecorded(vec![ApprovalRecordedData {
+      receiver_id: receiver_id.clone(),
+      created_at: create_epoch,
+      transaction_id: transaction_id.clone(),
+      approver_id,
+      approved,
+      status: status.clone(),
+    }])
+    .emit();
+    if status == "REJECTED" {
+      self.internal_refund_transaction(receiver_id, create_epoch, transaction_id, transaction);
+    } else {
+      self.internal_save_transaction(&receiver_id, create_epoch, &transaction_id, &transaction);
+    }
+  }
+
+  // Marks a locked transaction refunded and returns its whole locked balance to the sender
+  fn internal_refund_transaction(
+    &mut self,
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+    mut transaction: TransferTransaction,
+  ) {
+    let mut account = self.accounts.get(&receiver_id).unwrap();
+    transaction.status = "REFUND".to_string();
+    transaction.refunded_at = Some(env::epoch_height());
+    let fee = std::mem::take(&mut transaction.held_fee);
+    account.locked_balance = account
+      .locked_balance
+      .checked_sub(transaction.locked_balance)
+      .unwrap();
+    account.total_revenue = account
+      .total_revenue
+      .checked_sub(transaction.locked_balance)
+      .unwrap();
+    self.internal_save_transaction(&receiver_id, create_epoch, &transaction_id, &transaction);
+    self.totals.total_locked -= transaction.locked_balance;
+    self.totals.total_refunded += transaction.locked_balance;
+    self.totals.total_held_fees -= fee;
+    self.internal_record_campaign_refund(
+      &transaction.campaign_id,
+      &receiver_id,
+      transaction.locked_balance,
+      true,
+    );
+    self.internal_record_dashboard_settle(&transaction.sender_id, "REFUND");
+    HurdleEvent::TransactionRefunded(vec![TransactionRefundedData {
+      sender_id: transaction.sender_id.clone(),
+      receiver_id: receiver_id.clone(),
+      created_at: create_epoch,
+      transaction_id,
+      amount: U128(transaction.locked_balance),
+    }])
+    .emit();
+    // The sender's fee comes back with the payment
+    self.internal_return_to_sender(
+      &transaction.sender_id,
+      &transaction.organization_id,
+      transaction.locked_balance + fee,
+    );
+    self.accounts.insert(&receiver_id, &account);
+  }
+
+  // Organization payments go back to the organization's balance, others to the sender's account
+  fn internal_return_to_sender(
+    &mut self,
+    sender_id: &AccountId,
+    organization_id: &Option<String>,
+    amount: Balance,
+  ) {
+    match organization_id {
+      Some(organization_id) => {
+        let mut organization = self.organizations.get(organization_id).unwrap();
+        organization.prepaid_balance += amount;
+        self.organizations.insert(organization_id, &organization);
+        self.totals.total_prepaid += amount;
+      }
+      None => {
+        Promise::new(sender_id.clone()).transfer(amount);
       }
     }
   }
@@ -133,22 +710,29 @@ impl HurdlePayment {
     receiver_id: AccountId,
     cash_hold_time: u64,
   ) {
+    self.internal_screen_sender(&sender_id);
     let account = self.accounts.get(&receiver_id);
     assert!(account.is_some(), "Account not found");
     let mut account = account.unwrap();
     let mut start_epoch = env::epoch_height() - cash_hold_time * 2;
     let mut transer_amount = 0;
+    let mut fee_amount = 0;
+    let mut refunded = Vec::new();
     while start_epoch <= env::epoch_height() {
       let transactions = account.transactions.get(&start_epoch);
-      if transactions.is_some() {
-        let mut transactions = transactions.unwrap();
+      if let Some(mut transactions) = transactions {
         for transaction in transactions.to_vec() {
           let (transaction_id, mut transaction) = transaction;
-          if env::epoch_height() < transaction.claimable_at
+          // Organization payments are refunded one at a time by a refunder
+          if env::epoch_height() < transaction.refundable_until
+            && transaction.organization_id.is_none()
+            && transaction.status == "LOCK"
             && sender_id == transaction.sender_id
             && receiver_id == transaction.receiver_id
           {
             transaction.status = "REFUND".to_string();
+            transaction.refunded_at = Some(env::epoch_height());
+            fee_amount += std::mem::take(&mut transaction.held_fee);
             transactions.insert(&transaction_id, &transaction);
             account.locked_balance = account
               .locked_balance
@@ -159,12 +743,1018 @@ impl HurdlePayment {
               .checked_sub(transaction.locked_balance)
               .unwrap();
             transer_amount += transaction.locked_balance;
+            self.internal_record_campaign_refund(
+              &transaction.campaign_id,
+              &receiver_id,
+              transaction.locked_balance,
+              true,
+            );
+            self.internal_record_dashboard_settle(&transaction.sender_id, "REFUND");
+            refunded.push(TransactionRefundedData {
+              sender_id: sender_id.clone(),
+              receiver_id: receiver_id.clone(),
+              created_at: start_epoch,
+              transaction_id,
+              amount: U128(transaction.locked_balance),
+            });
           }
         }
       }
       start_epoch += 1;
     }
-    Promise::new(sender_id.clone()).transfer(transer_amount);
+    self.totals.total_locked -= transer_amount;
+    self.totals.total_refunded += transer_amount;
+    self.totals.total_held_fees -= fee_amount;
+    if !refunded.is_empty() {
+      HurdleEvent::TransactionRefunded(refunded).emit();
+    }
+    Promise::new(sender_id.clone()).transfer(transer_amount + fee_amount);
+    self.accounts.insert(&receiver_id, &account);
+  }
+
+  pub(crate) fn internal_get_transaction(
+    &self,
+    receiver_id: &AccountId,
+    create_epoch: u64,
+    transaction_id: &String,
+  ) -> TransferTransaction {
+    let transaction = self
+      .accounts
+      .get(receiver_id)
+      .and_then(|account| account.transactions.get(&create_epoch))
+      .and_then(|transactions| transactions.get(transaction_id));
+    assert!(transaction.is_some(), "Transaction not found");
+    transaction.unwrap()
+  }
+
+  pub(crate) fn internal_get_milestone(
+    &self,
+    receiver_id: &AccountId,
+    create_epoch: u64,
+    transaction_id: &String,
+    milestone_index: u64,
+  ) -> (TransferTransaction, Milestone) {
+    let transaction = self.internal_get_transaction(receiver_id, create_epoch, transaction_id);
+    let milestone = match &transaction.schedule {
+      PayoutSchedule::Milestones { milestones } => {
+        milestones.get(milestone_index as usize).cloned()
+      }
+      _ => None,
+    };
+    assert!(milestone.is_some(), "Milestone not found");
+    (transaction, milestone.unwrap())
+  }
+
+  fn internal_save_transaction(
+    &mut self,
+    receiver_id: &AccountId,
+    create_epoch: u64,
+    transaction_id: &String,
+    transaction: &TransferTransaction,
+  ) {
+    let account = self.accounts.get(receiver_id).unwrap();
+    let mut transactions = account.transactions.get(&create_epoch).unwrap();
+    transactions.insert(transaction_id, transaction);
+  }
+
+  // Releasing makes the milestone claimable once the hold time is over, it can no longer be
+  // reclaimed after that
+  pub(crate) fn internal_release_milestone(
+    &mut self,
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+    milestone_index: u64,
+  ) {
+    let (mut transaction, milestone) =
+      self.internal_get_milestone(&receiver_id, create_epoch, &transaction_id, milestone_index);
+    assert!(
+      milestone.released_at.is_none() && milestone.reclaimed_at.is_none(),
+      "Milestone already settled"
+    );
+    if let PayoutSchedule::Milestones { milestones } = &mut transaction.schedule {
+      milestones[milestone_index as usize].released_at = Some(env::epoch_height());
+    }
+    self.internal_save_transaction(&receiver_id, create_epoch, &transaction_id, &transaction);
+    HurdleEvent::MilestoneReleased(vec![MilestoneData {
+      sender_id: transaction.sender_id.clone(),
+      receiver_id,
+      created_at: create_epoch,
+      transaction_id,
+      milestone_index,
+      amount: U128(transaction.milestone_amount(milestone_index as usize)),
+      status: transaction.status,
+    }])
+    .emit();
+  }
+
+  // Returns every unreleased milestone past its deadline to the sender
+  pub(crate) fn internal_reclaim_milestones(
+    &mut self,
+    receiver_id: AccountId,
+    create_epoch: u64,
+    transaction_id: String,
+  ) -> Balance {
+    let mut transaction =
+      self.internal_get_transaction(&receiver_id, create_epoch, &transaction_id);
+    match &transaction.organization_id {
+      Some(organization_id) => {
+        self.internal_assert_organization_role(organization_id, &["ADMIN", "REFUNDER"]);
+      }
+      None => assert_eq!(
+        env::predecessor_account_id(),
+        transaction.sender_id,
+        "Only the sender can reclaim milestones"
+      ),
+    }
+    // Past their deadline the milestones can only go back, so blocked senders reclaim them too
+    let milestone_count = match &transaction.schedule {
+      PayoutSchedule::Milestones { milestones } => milestones.len(),
+      _ => panic!("Transaction has no milestones"),
+    };
+    let amounts: Vec<Balance> = (0..milestone_count)
+      .map(|index| transaction.milestone_amount(index))
+      .collect();
+    let claimable_at = transaction.claimable_at;
+    let mut reclaimed = Vec::new();
+    if let PayoutSchedule::Milestones { milestones } = &mut transaction.schedule {
+      for (index, milestone) in milestones.iter_mut().enumerate() {
+        if milestone.released_at.is_none()
+          && milestone.reclaimed_at.is_none()
+          && env::epoch_height() >= claimable_at.saturating_add(milestone.deadline)
+        {
+          milestone.reclaimed_at = Some(env::epoch_height());
+          reclaimed.push((index as u64, amounts[index]));
+        }
+      }
+    }
+    assert!(!reclaimed.is_empty(), "No milestone past its deadline");
+    let amount: Balance = reclaimed.iter().map(|(_, amount)| amount).sum();
+    let fee = transaction.release_fee(amount);
+    if transaction.outstanding_amount() == 0 {
+      transaction.status = if transaction.claimed_amount > 0 {
+        "CLAIM".to_string()
+      } else {
+        transaction.refunded_at = Some(env::epoch_height());
+        "REFUND".to_string()
+      };
+      self.internal_record_dashboard_settle(&transaction.sender_id, &transaction.status);
+    }
+    let mut account = self.accounts.get(&receiver_id).unwrap();
+    account.locked_balance = account.locked_balance.checked_sub(amount).unwrap();
+    account.total_revenue = account.total_revenue.checked_sub(amount).unwrap();
     self.accounts.insert(&receiver_id, &account);
+    self.internal_save_transaction(&receiver_id, create_epoch, &transaction_id, &transaction);
+    self.totals.total_locked -= amount;
+    self.totals.total_refunded += amount;
+    self.totals.total_held_fees -= fee;
+    self.internal_record_campaign_refund(
+      &transaction.campaign_id,
+      &receiver_id,
+      amount,
+      transaction.status == "REFUND",
+    );
+    HurdleEvent::MilestoneReclaimed(
+      reclaimed
+        .into_iter()
+        .map(|(milestone_index, amount)| MilestoneData {
+          sender_id: transaction.sender_id.clone(),
+          receiver_id: receiver_id.clone(),
+          created_at: create_epoch,
+          transaction_id: transaction_id.clone(),
+          milestone_index,
+          amount: U128(amount),
+          status: transaction.status.clone(),
+        })
+        .collect(),
+    )
+    .emit();
+    self.internal_return_to_sender(
+      &transaction.sender_id,
+      &transaction.organization_id,
+      amount + fee,
+    );
+    amount
+  }
+
+  pub(crate) fn internal_deposit_prepaid_balance(
+    &mut self,
+    account_id: &AccountId,
+    amount: Balance,
+  ) {
+    let balance = self.prepaid_balances.get(account_id).unwrap_or(0);
+    self
+      .prepaid_balances
+      .insert(account_id, &(balance + amount));
+    self.totals.total_prepaid += amount;
+  }
+
+  pub(crate) fn internal_withdraw_prepaid_balance(
+    &mut self,
+    account_id: &AccountId,
+    amount: Balance,
+  ) {
+    let balance = self.prepaid_balances.get(account_id).unwrap_or(0);
+    assert!(balance >= amount, "Not enough prepaid balance");
+    self
+      .prepaid_balances
+      .insert(account_id, &(balance - amount));
+    self.totals.total_prepaid -= amount;
+  }
+
+  pub(crate) fn internal_redeem_voucher(&mut self, voucher: Voucher, signature: Vec<u8>) {
+    assert_user_transaction_id(&voucher.transaction_id);
+    assert!(env::epoch_height() <= voucher.expires_at, "Voucher expired");
+    let public_key = self.voucher_keys.get(&voucher.sender_id);
+    assert!(public_key.is_some(), "Voucher key not registered");
+    assert!(
+      verify_voucher_signature(
+        &env::current_account_id(),
+        &public_key.unwrap(),
+        &voucher,
+        &signature
+      ),
+      "Invalid voucher signature"
+    );
+    let nonce_key = (voucher.sender_id.clone(), voucher.nonce);
+    assert!(
+      !self.consumed_nonces.contains(&nonce_key),
+      "Voucher already redeemed"
+    );
+
+    let before_storage_usage = env::storage_usage();
+    self.consumed_nonces.insert(&nonce_key);
+    let platform_fee = match self.internal_create_transfer_transaction(
+      voucher.sender_id.clone(),
+      voucher.receiver_id,
+      voucher.amount.0,
+      voucher.cash_hold_time * 2,
+      None,
+      PayoutSchedule::Lump,
+      voucher.campaign_id,
+      voucher.transaction_id,
+      None,
+    ) {
+      Some(platform_fee) => platform_fee,
+      None => {
+        // A rejected voucher can be redeemed again once the window has room
+        self.consumed_nonces.remove(&nonce_key);
+        return;
+      }
+    };
+    let after_storage_usage = env::storage_usage();
+    // The sender's prepaid balance covers both the payment and the storage it uses
+    let storage_cost =
+      env::storage_byte_cost() * Balance::from(after_storage_usage - before_storage_usage);
+    self.internal_withdraw_prepaid_balance(
+      &voucher.sender_id,
+      voucher.amount.0 + platform_fee + storage_cost,
+    );
+  }
+
+  fn internal_get_campaign_stats(&self, campaign_id: &String) -> CampaignStats {
+    self
+      .campaign_stats
+      .get(campaign_id)
+      .unwrap_or_else(|| CampaignStats::new(campaign_id))
+  }
+
+  pub(crate) fn internal_record_campaign_lock(&mut self, transaction: &TransferTransaction) {
+    let mut stats = self.internal_get_campaign_stats(&transaction.campaign_id);
+    stats.transaction_count += 1;
+    stats.total_locked += transaction.locked_balance;
+    stats.total_hold_time += transaction.claimable_at - transaction.created_at;
+    let received = stats.received(&transaction.receiver_id);
+    stats.set_received(
+      &transaction.receiver_id,
+      received + transaction.locked_balance,
+    );
+    self.campaign_stats.insert(&transaction.campaign_id, &stats);
+  }
+
+  pub(crate) fn internal_record_campaign_claim(&mut self, campaign_id: &String, amount: Balance) {
+    let mut stats = self.internal_get_campaign_stats(campaign_id);
+    stats.total_claimed += amount;
+    self.campaign_stats.insert(campaign_id, &stats);
+  }
+
+  // Milestone reclaims refund part of a transaction, it only counts as refunded once settled
+  pub(crate) fn internal_record_campaign_refund(
+    &mut self,
+    campaign_id: &String,
+    receiver_id: &AccountId,
+    amount: Balance,
+    refunded: bool,
+  ) {
+    let mut stats = self.internal_get_campaign_stats(campaign_id);
+    if refunded {
+      stats.refunded_count += 1;
+    }
+    stats.total_refunded += amount;
+    let received = stats.received(receiver_id);
+    stats.set_received(receiver_id, received.saturating_sub(amount));
+    self.campaign_stats.insert(campaign_id, &stats);
+  }
+
+  pub(crate) fn internal_record_dashboard_lock(&mut self, sender_id: &AccountId) {
+    self.dashboard.locked_transactions += 1;
+    let open_transactions = self.open_transactions_by_sender.get(sender_id).unwrap_or(0);
+    if open_transactions == 0 {
+      self.dashboard.active_senders += 1;
+    }
+    self
+      .open_transactions_by_sender
+      .insert(sender_id, &(open_transactions + 1));
+  }
+
+  // Moves a transaction out of LOCK into the CLAIM or REFUND count
+  pub(crate) fn internal_record_dashboard_settle(&mut self, sender_id: &AccountId, status: &str) {
+    self.dashboard.locked_transactions -= 1;
+    match status {
+      "CLAIM" => self.dashboard.claimed_transactions += 1,
+      _ => self.dashboard.refunded_transactions += 1,
+    }
+    let open_transactions = self.open_transactions_by_sender.get(sender_id).unwrap_or(1) - 1;
+    if open_transactions == 0 {
+      self.dashboard.active_senders -= 1;
+      self.open_transactions_by_sender.remove(sender_id);
+    } else {
+      self
+        .open_transactions_by_sender
+        .insert(sender_id, &open_transactions);
+    }
+  }
+
+  pub(crate) fn internal_record_dashboard_claim(&mut self, amount: Balance) {
+    let current_epoch = env::epoch_height();
+    let slot = current_epoch % DASHBOARD_EPOCHS;
+    let claimed = self
+      .claimed_by_epoch
+      .get(&slot)
+      .filter(|(claimed_at, _)| *claimed_at == current_epoch)
+      .map_or(0, |(_, claimed)| claimed);
+    self
+      .claimed_by_epoch
+      .insert(&slot, &(current_epoch, claimed + amount));
+  }
+
+  pub(crate) fn internal_get_campaign_as_owner(&self, campaign_id: &String) -> Campaign {
+    let campaign = self.campaigns.get(campaign_id);
+    assert!(campaign.is_some(), "Campaign not found");
+    let campaign = campaign.unwrap();
+    match &campaign.organization_id {
+      Some(organization_id) => {
+        self.internal_assert_organization_role(organization_id, &["ADMIN"]);
+      }
+      None => assert_eq!(
+        env::predecessor_account_id(),
+        campaign.owner_id,
+        "Only the campaign owner can update the campaign"
+      ),
+    }
+    campaign
+  }
+
+  // Campaign settings only bind the payments of the campaign's owner, anyone can tag a payment
+  // with a campaign id they do not own
+  pub(crate) fn internal_sender_campaign(
+    &self,
+    sender_id: &AccountId,
+    campaign_id: &String,
+    organization_id: &Option<String>,
+  ) -> Option<Campaign> {
+    self
+      .campaigns
+      .get(campaign_id)
+      .filter(|campaign| match &campaign.organization_id {
+        Some(campaign_organization_id) => {
+          organization_id.as_ref() == Some(campaign_organization_id)
+        }
+        None => organization_id.is_none() && &campaign.owner_id == sender_id,
+      })
+  }
+
+  // Returns the organization when the caller holds one of `roles` in it
+  pub(crate) fn internal_assert_organization_role(
+    &self,
+    organization_id: &String,
+    roles: &[&str],
+  ) -> Organization {
+    let organization = self.organizations.get(organization_id);
+    assert!(organization.is_some(), "Organization not found");
+    let organization = organization.unwrap();
+    assert!(
+      organization.has_role(&env::predecessor_account_id(), roles),
+      "Requires the {} role in organization {}",
+      roles.join(" or "),
+      organization_id
+    );
+    organization
+  }
+
+  pub(crate) fn internal_set_organization_member(
+    &mut self,
+    organization_id: &String,
+    account_id: AccountId,
+    role: Option<String>,
+  ) {
+    let mut organization = self.internal_assert_organization_role(organization_id, &["ADMIN"]);
+    organization
+      .members
+      .retain(|(member_id, _)| member_id != &account_id);
+    if let Some(role) = role {
+      assert!(
+        ORGANIZATION_ROLES.contains(&role.as_str()),
+        "Unknown role {}",
+        role
+      );
+      assert!(
+        env::is_valid_account_id(account_id.as_bytes()),
+        "Invalid account id"
+      );
+      organization.members.push((account_id, role));
+    }
+    assert!(
+      organization.members.iter().any(|(_, role)| role == "ADMIN"),
+      "An organization needs at least one admin"
+    );
+    self.organizations.insert(organization_id, &organization);
+  }
+
+  // The organization's balance covers both the payment and the storage it uses
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn internal_send_from_organization(
+    &mut self,
+    organization_id: String,
+    receiver_id: AccountId,
+    amount: Balance,
+    cash_hold_time: i64,
+    refund_window: Option<u64>,
+    schedule: PayoutSchedule,
+    campaign_id: String,
+    transaction_id: String,
+  ) {
+    assert_user_transaction_id(&transaction_id);
+    let member_id = env::predecessor_account_id();
+    self.internal_assert_organization_role(&organization_id, &["ADMIN", "SPENDER"]);
+    let before_storage_usage = env::storage_usage();
+    let platform_fee = match self.internal_create_transfer_transaction(
+      member_id,
+      receiver_id,
+      amount,
+      cash_hold_time,
+      refund_window,
+      schedule,
+      campaign_id,
+      transaction_id,
+      Some(organization_id.clone()),
+    ) {
+      Some(platform_fee) => platform_fee,
+      None => return,
+    };
+    let after_storage_usage = env::storage_usage();
+    let storage_cost = env::storage_byte_cost()
+      * Balance::from(after_storage_usage.saturating_sub(before_storage_usage));
+    let cost = amount + platform_fee + storage_cost;
+    let mut organization = self.organizations.get(&organization_id).unwrap();
+    assert!(
+      organization.prepaid_balance >= cost,
+      "Not enough balance in organization {}",
+      organization_id
+    );
+    organization.prepaid_balance -= cost;
+    self.organizations.insert(&organization_id, &organization);
+    self.totals.total_prepaid -= cost;
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn internal_create_subscription(
+    &mut self,
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    campaign_id: String,
+    amount: Balance,
+    period: u64,
+    cash_hold_time: u64,
+    max_periods: u64,
+  ) -> u64 {
+    assert!(
+      self.accounts.get(&receiver_id).is_some(),
+      "Account not found"
+    );
+    assert!(period > 0, "Subscription period must be positive");
+    let subscription_id = self.next_subscription_id;
+    self.next_subscription_id += 1;
+    let subscription = Subscription {
+      sender_id: sender_id.clone(),
+      receiver_id: receiver_id.clone(),
+      campaign_id,
+      amount,
+      period,
+      cash_hold_time,
+      max_periods,
+      charged_periods: 0,
+      next_charge_at: env::epoch_height(),
+      status: "ACTIVE".to_string(),
+    };
+    self.subscriptions.insert(&subscription_id, &subscription);
+    self.open_subscriptions.insert(&subscription_id);
+    self.internal_index_subscription(&sender_id, subscription_id);
+    self.internal_index_subscription(&receiver_id, subscription_id);
+    subscription_id
+  }
+
+  fn internal_index_subscription(&mut self, account_id: &AccountId, subscription_id: u64) {
+    let mut subscription_ids = self
+      .subscriptions_by_account
+      .get(account_id)
+      .unwrap_or_default();
+    subscription_ids.push(subscription_id);
+    self
+      .subscriptions_by_account
+      .insert(account_id, &subscription_ids);
+  }
+
+  pub(crate) fn internal_charge_subscription(
+    &mut self,
+    subscription_id: u64,
+    subscription: &mut Subscription,
+  ) -> u64 {
+    let mut charged = 0;
+    while subscription.status == "ACTIVE"
+      && subscription.next_charge_at <= env::epoch_height()
+      && charged < MAX_CATCH_UP_PERIODS
+    {
+      // A charge must not panic, that would undo every other charge of the page
+      if let Some(reason) = self.internal_subscription_skip_reason(subscription) {
+        env::log(format!("Subscription {} skipped: {}", subscription_id, reason).as_bytes());
+        break;
+      }
+
+      let before_storage_usage = env::storage_usage();
+      let platform_fee = match self.internal_create_transfer_transaction(
+        subscription.sender_id.clone(),
+        subscription.receiver_id.clone(),
+        subscription.amount,
+        subscription.cash_hold_time as i64,
+        None,
+        PayoutSchedule::Lump,
+        subscription.campaign_id.clone(),
+        format!(
+          "subscription-{}-{}",
+          subscription_id, subscription.charged_periods
+        ),
+        None,
+      ) {
+        Some(platform_fee) => platform_fee,
+        None => break,
+      };
+      let after_storage_usage = env::storage_usage();
+      let storage_cost =
+        env::storage_byte_cost() * Balance::from(after_storage_usage - before_storage_usage);
+      self.internal_withdraw_prepaid_balance(
+        &subscription.sender_id,
+        subscription.amount + platform_fee + storage_cost,
+      );
+
+      subscription.charged_periods += 1;
+      subscription.next_charge_at += subscription.period;
+      if subscription.charged_periods == subscription.max_periods {
+        subscription.status = "COMPLETED".to_string();
+      }
+      charged += 1;
+    }
+    charged
+  }
+
+  fn internal_subscription_skip_reason(&self, subscription: &Subscription) -> Option<String> {
+    let prepaid_balance = self
+      .prepaid_balances
+      .get(&subscription.sender_id)
+      .unwrap_or(0);
+    let max_storage_cost = env::storage_byte_cost() * Balance::from(TRANSACTION_STORAGE_ESTIMATE);
+    let platform_fee = self.internal_platform_fee(subscription.amount);
+    if prepaid_balance < subscription.amount + platform_fee + max_storage_cost {
+      Some("not enough prepaid balance".to_string())
+    } else if !self.internal_can_send(&subscription.sender_id) {
+      Some(format!("sender {} is screened out", subscription.sender_id))
+    } else if !self.internal_can_receive(&subscription.receiver_id, subscription.amount) {
+      Some(format!(
+        "receiver {} cannot take the payment",
+        subscription.receiver_id
+      ))
+    } else {
+      None
+    }
+  }
+
+  pub(crate) fn internal_set_subscription_status(&mut self, subscription_id: u64, status: &str) {
+    let subscription = self.subscriptions.get(&subscription_id);
+    assert!(subscription.is_some(), "Subscription not found");
+    let mut subscription = subscription.unwrap();
+    assert_eq!(
+      env::predecessor_account_id(),
+      subscription.sender_id,
+      "Only the sender can update the subscription"
+    );
+    assert!(
+      subscription.status == "ACTIVE" || subscription.status == "PAUSED",
+      "Subscription is {}",
+      subscription.status
+    );
+    if status == "ACTIVE" && subscription.status == "PAUSED" {
+      // Periods skipped while paused are not charged afterwards
+      subscription.next_charge_at = std::cmp::max(subscription.next_charge_at, env::epoch_height());
+    }
+    subscription.status = status.to_string();
+    self.subscriptions.insert(&subscription_id, &subscription);
+    if status == "CANCELLED" {
+      self.open_subscriptions.remove(&subscription_id);
+    }
+  }
+
+  pub(crate) fn internal_get_stream(&self, stream_id: u64) -> Stream {
+    let stream = self.streams.get(&stream_id);
+    assert!(stream.is_some(), "Stream not found");
+    stream.unwrap()
+  }
+
+  pub(crate) fn internal_withdraw_from_stream(&mut self, stream_id: u64) -> Balance {
+    let mut stream = self.internal_get_stream(stream_id);
+    assert_eq!(
+      env::predecessor_account_id(),
+      stream.receiver_id,
+      "Only the receiver can withdraw from the stream"
+    );
+    self.internal_screen_receiver(&stream.receiver_id);
+    let amount = stream.accrued_amount(now_in_seconds()) - stream.withdrawn;
+    stream.withdrawn += amount;
+    self.streams.insert(&stream_id, &stream);
+
+    // A receiver that closed its account still gets what accrued, there is just no revenue to book
+    if let Some(mut account) = self.accounts.get(&stream.receiver_id) {
+      account.total_revenue += amount;
+      self.accounts.insert(&stream.receiver_id, &account);
+    }
+    self.totals.total_streaming -= amount;
+    if amount > 0 {
+      HurdleEvent::StreamWithdrawn(vec![StreamWithdrawnData {
+        stream_id,
+        sender_id: stream.sender_id,
+        receiver_id: stream.receiver_id.clone(),
+        amount: U128(amount),
+      }])
+      .emit();
+      Promise::new(stream.receiver_id).transfer(amount);
+    }
+    amount
+  }
+
+  pub(crate) fn internal_stop_stream(&mut self, stream_id: u64) -> Balance {
+    let mut stream = self.internal_get_stream(stream_id);
+    assert_eq!(
+      env::predecessor_account_id(),
+      stream.sender_id,
+      "Only the sender can stop the stream"
+    );
+    self.internal_screen_sender(&stream.sender_id);
+    assert_eq!(stream.status, "ACTIVE", "Stream is already stopped");
+    let now = now_in_seconds();
+    stream.stopped_at = Some(std::cmp::max(now, stream.started_at));
+    let accrued = stream.accrued_amount(now);
+    // Whatever has not accrued yet goes back to the sender, the rest stays withdrawable
+    let remainder = stream.deposit - accrued;
+    stream.deposit = accrued;
+    stream.status = "STOPPED".to_string();
+    self.streams.insert(&stream_id, &stream);
+    self.totals.total_streaming -= remainder;
+    if remainder > 0 {
+      Promise::new(stream.sender_id).transfer(remainder);
+    }
+    remainder
+  }
+
+  pub(crate) fn internal_get_invoice(&self, invoice_id: &String) -> Invoice {
+    let invoice = self.invoices.get(invoice_id);
+    assert!(invoice.is_some(), "Invoice not found");
+    invoice.unwrap()
+  }
+
+  // Returns what the payer owes, the invoiced amount plus the platform fee
+  pub(crate) fn internal_pay_invoice(&mut self, invoice_id: String) -> Balance {
+    let mut invoice = self.internal_get_invoice(&invoice_id);
+    assert_eq!(invoice.current_status(), "OPEN", "Invoice is not open");
+    let payer_id = env::predecessor_account_id();
+    if let Some(allowed_payer_id) = &invoice.payer_id {
+      assert_eq!(
+        &payer_id, allowed_payer_id,
+        "Invoice can only be paid by {}",
+        allowed_payer_id
+      );
+    }
+    let platform_fee = match self.internal_create_transfer_transaction(
+      payer_id,
+      invoice.issuer_id.clone(),
+      invoice.amount,
+      invoice.cash_hold_time * 2,
+      None,
+      PayoutSchedule::Lump,
+      invoice.campaign_id.clone(),
+      invoice_id.clone(),
+      None,
+    ) {
+      Some(platform_fee) => platform_fee,
+      // The invoice stays open and the whole deposit goes back to the payer
+      None => return 0,
+    };
+    invoice.status = "PAID".to_string();
+    self.invoices.insert(&invoice_id, &invoice);
+    invoice.amount + platform_fee
+  }
+
+  pub(crate) fn internal_cancel_invoice(&mut self, invoice_id: String) {
+    let mut invoice = self.internal_get_invoice(&invoice_id);
+    assert_eq!(
+      env::predecessor_account_id(),
+      invoice.issuer_id,
+      "Only the issuer can cancel the invoice"
+    );
+    assert_eq!(invoice.status, "OPEN", "Invoice is not open");
+    invoice.status = "CANCELLED".to_string();
+    self.invoices.insert(&invoice_id, &invoice);
+  }
+
+  pub(crate) fn internal_assert_owner(&self) {
+    assert_eq!(
+      env::predecessor_account_id(),
+      self.owner_id,
+      "Only the contract owner can call this method"
+    );
+  }
+
+  fn internal_is_allowlisted(&self, account_id: &AccountId) -> bool {
+    !self.allowlist_enabled || self.allowed_accounts.contains(account_id)
+  }
+
+  pub(crate) fn internal_screen_sender(&self, account_id: &AccountId) {
+    assert!(
+      !self.blocked_senders.contains(account_id),
+      "Sender {} is blocked",
+      account_id
+    );
+    assert!(
+      self.internal_is_allowlisted(account_id),
+      "Sender {} is not on the allowlist",
+      account_id
+    );
+  }
+
+  pub(crate) fn internal_screen_receiver(&self, account_id: &AccountId) {
+    assert!(
+      !self.blocked_receivers.contains(account_id),
+      "Receiver {} is blocked",
+      account_id
+    );
+    assert!(
+      self.internal_is_allowlisted(account_id),
+      "Receiver {} is not on the allowlist",
+      account_id
+    );
+  }
+
+  // Records the payment against the sender and campaign windows, or logs the rejection
+  fn internal_check_velocity(
+    &mut self,
+    sender_id: &AccountId,
+    campaign_id: &String,
+    campaign_payment: bool,
+    amount: Balance,
+  ) -> bool {
+    let now = now_in_seconds();
+    let sender_limit = self.sender_velocity_limits.get(sender_id);
+    let campaign_limit = if campaign_payment {
+      self.campaign_velocity_limits.get(campaign_id)
+    } else {
+      None
+    };
+    let mut sender_tracker = self.sender_velocity.get(sender_id).unwrap_or_default();
+    let mut campaign_tracker = self.campaign_velocity.get(campaign_id).unwrap_or_default();
+
+    let checks = [
+      ("sender", &sender_limit, &mut sender_tracker),
+      ("campaign", &campaign_limit, &mut campaign_tracker),
+    ];
+    for (scope, limit, tracker) in checks {
+      if let Some(limit) = limit {
+        tracker.prune(now, limit.window);
+        if let Some(exceeded) = tracker.exceeded_limit(limit, amount) {
+          HurdleEvent::VelocityLimitExceeded(vec![VelocityLimitExceededData {
+            scope: scope.to_string(),
+            limit: exceeded.to_string(),
+            sender_id: sender_id.clone(),
+            campaign_id: campaign_id.clone(),
+            amount: U128(amount),
+            window: limit.window,
+          }])
+          .emit();
+          return false;
+        }
+      }
+    }
+
+    if let Some(limit) = sender_limit {
+      sender_tracker.record(now, limit.window, amount);
+      self.sender_velocity.insert(sender_id, &sender_tracker);
+    }
+    if let Some(limit) = campaign_limit {
+      campaign_tracker.record(now, limit.window, amount);
+      self
+        .campaign_velocity
+        .insert(campaign_id, &campaign_tracker);
+    }
+    true
+  }
+
+  // Clears every settled epoch bucket older than the retention period. Senders get back their
+  // transactions' storage, the bucket itself goes back to whoever opened it
+  pub(crate) fn internal_prune_settled_epochs(&mut self, account_id: &AccountId) {
+    let account = self.accounts.get(account_id);
+    assert!(account.is_some(), "Account not found");
+    let mut account = account.unwrap();
+    let retention_ends_at = env::epoch_height().saturating_sub(self.retention_period);
+    for (epoch, mut transactions) in account.transactions.to_vec() {
+      if epoch >= retention_ends_at {
+        continue;
+      }
+      let settled = transactions
+        .values()
+        .all(|transaction| transaction.status != "LOCK");
+      if !settled {
+        continue;
+      }
+      // The epoch's first transaction created the bucket, so its sender paid for it
+      let opener = transactions
+        .values_as_vector()
+        .get(0)
+        .map(|transaction| (transaction.sender_id, transaction.organization_id));
+      let mut refunds = self.internal_clear_transactions(&mut transactions);
+      let before_storage_usage = env::storage_usage();
+      account.transactions.remove(&epoch);
+      if let Some(opener) = opener {
+        *refunds.entry(opener).or_default() += before_storage_usage - env::storage_usage();
+      }
+      self.internal_refund_storage(refunds);
+    }
+    self.accounts.insert(account_id, &account);
+  }
+
+  // Returns the released storage per sender, senders paid for their transactions' storage
+  fn internal_clear_transactions(
+    &mut self,
+    transactions: &mut UnorderedMap<String, TransferTransaction>,
+  ) -> BTreeMap<(AccountId, Option<String>), u64> {
+    let mut refunds: BTreeMap<(AccountId, Option<String>), u64> = BTreeMap::new();
+    for (transaction_id, transaction) in transactions.to_vec() {
+      let before_storage_usage = env::storage_usage();
+      transactions.remove(&transaction_id);
+      *refunds
+        .entry((transaction.sender_id, transaction.organization_id))
+        .or_default() += before_storage_usage - env::storage_usage();
+    }
+    transactions.clear();
+    refunds
+  }
+
+  // Organizations get released storage back into their prepaid balance, anyone else by transfer
+  fn internal_refund_storage(&mut self, refunds: BTreeMap<(AccountId, Option<String>), u64>) {
+    for ((sender_id, organization_id), released_storage) in refunds {
+      let amount = env::storage_byte_cost() * Balance::from(released_storage);
+      match organization_id.and_then(|organization_id| {
+        self
+          .organizations
+          .get(&organization_id)
+          .map(|organization| (organization_id, organization))
+      }) {
+        Some((organization_id, mut organization)) => {
+          organization.prepaid_balance += amount;
+          self.organizations.insert(&organization_id, &organization);
+          self.totals.total_prepaid += amount;
+        }
+        None => {
+          Promise::new(sender_id).transfer(amount);
+        }
+      }
+    }
+  }
+
+  // Anything that still pays into the account would be left without a place to land
+  fn internal_assert_nothing_open(&self, account_id: &AccountId) {
+    let open_subscriptions = self
+      .subscriptions_by_account
+      .get(account_id)
+      .unwrap_or_default()
+      .into_iter()
+      .filter(|subscription_id| self.open_subscriptions.contains(subscription_id))
+      .filter_map(|subscription_id| self.subscriptions.get(&subscription_id))
+      .any(|subscription| &subscription.receiver_id == account_id);
+    assert!(!open_subscriptions, "Account still has open subscriptions");
+    let open_streams = self
+      .streams_by_account
+      .get(account_id)
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|stream_id| self.streams.get(&stream_id))
+      .any(|stream| stream.withdrawn < stream.deposit);
+    assert!(!open_streams, "Account still has open streams");
+    let open_invoices = self
+      .invoices_by_account
+      .get(account_id)
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|invoice_id| self.invoices.get(&invoice_id))
+      .any(|invoice| invoice.current_status() == "OPEN");
+    assert!(!open_invoices, "Account still has open invoices");
+  }
+
+  // Returns the released storage the account itself is owed, net of what the final claim used
+  pub(crate) fn internal_close_account(&mut self, account_id: &AccountId) -> u64 {
+    self.internal_assert_nothing_open(account_id);
+    let before_storage_usage = env::storage_usage();
+    self.internal_unlock_locked_balance(account_id.clone());
+    let mut account = self.accounts.get(account_id).unwrap();
+    // Pending approvals and milestones are still locked, so they keep the account open too
+    assert_eq!(
+      account.locked_balance, 0,
+      "Account still has locked balance"
+    );
+    let transactions_storage_usage = env::storage_usage();
+    for (_, mut transactions) in account.transactions.to_vec() {
+      let refunds = self.internal_clear_transactions(&mut transactions);
+      self.internal_refund_storage(refunds);
+    }
+    let senders_storage = transactions_storage_usage - env::storage_usage();
+    account.transactions.clear();
+    self.accounts.remove(account_id);
+    self.account_ids.remove(account_id);
+    self.internal_remove_referrer(account_id);
+    HurdleEvent::AccountClosed(vec![AccountEventData {
+      account_id: account_id.clone(),
+    }])
+    .emit();
+    before_storage_usage
+      .saturating_sub(env::storage_usage())
+      .saturating_sub(senders_storage)
+  }
+
+  // Version 0 keyed the epoch maps by epoch alone, so one map can hold other receivers'
+  // transactions too. Only the account's own transactions move into its new per-epoch maps
+  pub(crate) fn internal_migrate_account(&mut self, account_id: &AccountId) -> bool {
+    let old_account = match take_old_account(account_id) {
+      Some(old_account) => old_account,
+      None => return false,
+    };
+    // Same prefix as before, the epochs keep their slots and point at the new maps
+    let mut transactions: UnorderedMap<u64, UnorderedMap<String, TransferTransaction>> =
+      UnorderedMap::try_from_slice(&old_account.transactions.try_to_vec().unwrap()).unwrap();
+    for (epoch, old_transactions) in old_account.transactions.to_vec() {
+      let mut map = UnorderedMap::new(StorageKey::AccountTransaction {
+        account_hash: env::sha256(format!("{}:{}", account_id, epoch).as_bytes()),
+      });
+      for (transaction_id, old_transaction) in old_transactions.to_vec() {
+        if &old_transaction.receiver_id != account_id {
+          continue;
+        }
+        let transaction = old_transaction.migrate();
+        self.totals.total_escrowed += transaction.locked_balance;
+        match transaction.status.as_str() {
+          "CLAIM" => {
+            self.totals.total_claimed += transaction.locked_balance;
+            self.dashboard.claimed_transactions += 1;
+          }
+          "REFUND" => {
+            self.totals.total_refunded += transaction.locked_balance;
+            self.dashboard.refunded_transactions += 1;
+          }
+          _ => self.internal_record_dashboard_lock(&transaction.sender_id),
+        }
+        map.insert(&transaction_id, &transaction);
+      }
+      transactions.insert(&epoch, &map);
+    }
+    self.totals.total_locked += old_account.locked_balance;
+    let account = Account {
+      locked_balance: old_account.locked_balance,
+      total_revenue: old_account.total_revenue,
+      transactions,
+      last_unlock_at: old_account.last_unlock_at,
+      kyc_tier: 0,
+      claim_day: today(),
+      claimed_today: 0,
+      claim_hook_enabled: false,
+    };
+    self.accounts.insert(account_id, &account);
+    // Version 0 had no registry, the dashboard and `get_accounts` only see migrated accounts
+    self.account_ids.insert(account_id);
+    true
   }
 }
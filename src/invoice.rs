@@ -0,0 +1,57 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Invoice {
+  pub issuer_id: AccountId,
+  pub payer_id: Option<AccountId>,
+  pub campaign_id: String,
+  pub amount: Balance,
+  pub cash_hold_time: i64,
+  pub memo: String,
+  pub created_at: u64,
+  pub due_at: u64,
+  pub status: String, // OPEN PAID CANCELLED
+}
+
+impl Invoice {
+  // Open invoices past their due date are reported as EXPIRED
+  pub fn current_status(&self) -> String {
+    if self.status == "OPEN" && env::epoch_height() > self.due_at {
+      "EXPIRED".to_string()
+    } else {
+      self.status.clone()
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceJson {
+  pub invoice_id: String,
+  pub issuer_id: AccountId,
+  pub payer_id: Option<AccountId>,
+  pub campaign_id: String,
+  pub amount: U128,
+  pub cash_hold_time: i64,
+  pub memo: String,
+  pub created_at: u64,
+  pub due_at: u64,
+  pub status: String,
+}
+
+impl InvoiceJson {
+  pub fn from(invoice_id: String, invoice: Invoice) -> Self {
+    InvoiceJson {
+      invoice_id,
+      status: invoice.current_status(),
+      issuer_id: invoice.issuer_id,
+      payer_id: invoice.payer_id,
+      campaign_id: invoice.campaign_id,
+      amount: U128(invoice.amount),
+      cash_hold_time: invoice.cash_hold_time,
+      memo: invoice.memo,
+      created_at: invoice.created_at,
+      due_at: invoice.due_at,
+    }
+  }
+}
@@ -0,0 +1,37 @@
+use crate::*;
+
+// Limits that apply to receivers attested at a given KYC tier, None means unlimited
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TierLimit {
+  pub max_locked_balance: Option<Balance>,
+  pub max_claim_per_day: Option<Balance>,
+  pub max_lifetime_revenue: Option<Balance>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TierLimitJson {
+  pub max_locked_balance: Option<U128>,
+  pub max_claim_per_day: Option<U128>,
+  pub max_lifetime_revenue: Option<U128>,
+}
+
+impl TierLimit {
+  pub fn from(limit: TierLimitJson) -> Self {
+    TierLimit {
+      max_locked_balance: limit.max_locked_balance.map(|amount| amount.0),
+      max_claim_per_day: limit.max_claim_per_day.map(|amount| amount.0),
+      max_lifetime_revenue: limit.max_lifetime_revenue.map(|amount| amount.0),
+    }
+  }
+}
+
+impl TierLimitJson {
+  pub fn from(limit: TierLimit) -> Self {
+    TierLimitJson {
+      max_locked_balance: limit.max_locked_balance.map(U128),
+      max_claim_per_day: limit.max_claim_per_day.map(U128),
+      max_lifetime_revenue: limit.max_lifetime_revenue.map(U128),
+    }
+  }
+}
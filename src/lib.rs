@@ -1,26 +1,87 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap};
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{Base58PublicKey, Base64VecU8, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    env, near_bindgen, AccountId, Balance, BlockHeight, BorshStorageKey, PanicOnDefault, Promise,
-    PromiseOrValue,
+    env, near_bindgen, AccountId, Balance, BorshStorageKey, PanicOnDefault, Promise, PromiseResult,
 };
 
-use crate::internal::*;
 mod internal;
-use crate::util::*;
+pub use crate::util::*;
 mod util;
-use crate::account::*;
+pub use crate::account::*;
 mod account;
-use crate::transfer_transaction::*;
+pub use crate::transfer_transaction::*;
 mod transfer_transaction;
+pub use crate::voucher::*;
+mod voucher;
+pub use crate::campaign::*;
+mod campaign;
+pub use crate::subscription::*;
+mod subscription;
+pub use crate::stream::*;
+mod stream;
+pub use crate::invoice::*;
+mod invoice;
+pub use crate::kyc::*;
+mod kyc;
+pub use crate::event::*;
+mod event;
+pub use crate::velocity::*;
+mod velocity;
+pub use crate::accounting::*;
+mod accounting;
+pub use crate::statement::*;
+mod statement;
+pub use crate::dashboard::*;
+mod dashboard;
+pub use crate::referral::*;
+mod referral;
+pub use crate::milestone::*;
+mod milestone;
+pub use crate::organization::*;
+mod organization;
+pub use crate::claim_hook::*;
+mod claim_hook;
+pub use crate::callbacks::*;
+mod callbacks;
+pub use crate::migration::*;
+mod migration;
 
 #[derive(BorshDeserialize, BorshSerialize, BorshStorageKey)]
 pub enum StorageKey {
     AccountKey,
     AccountTransaction { account_hash: Vec<u8> },
     AccountTransactionByDate { account_hash: Vec<u8> },
+    PrepaidBalance,
+    VoucherKey,
+    ConsumedNonce,
+    CampaignKey,
+    SubscriptionKey,
+    SubscriptionsByAccount,
+    StreamKey,
+    InvoiceKey,
+    BlockedSender,
+    BlockedReceiver,
+    AllowedAccount,
+    Attester,
+    TierLimit,
+    SenderVelocityLimit,
+    CampaignVelocityLimit,
+    SenderVelocity,
+    CampaignVelocity,
+    CampaignStats,
+    CampaignReceivers { campaign_hash: Vec<u8> },
+    AccountIds,
+    OpenTransactionsBySender,
+    ClaimedByEpoch,
+    OpenSubscriptions,
+    Referrer,
+    Referrals,
+    Organization,
+    StreamsByAccount,
+    InvoicesByAccount,
+    CampaignRanking { campaign_hash: Vec<u8> },
 }
 
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -28,6 +89,44 @@ pub enum StorageKey {
 pub struct HurdlePayment {
     pub owner_id: AccountId,
     pub accounts: LookupMap<AccountId, Account>,
+    pub prepaid_balances: LookupMap<AccountId, Balance>,
+    pub voucher_keys: LookupMap<AccountId, Vec<u8>>,
+    pub consumed_nonces: LookupSet<(AccountId, u64)>,
+    pub campaigns: LookupMap<String, Campaign>,
+    pub subscriptions: LookupMap<u64, Subscription>,
+    pub open_subscriptions: UnorderedSet<u64>, // ACTIVE and PAUSED, the ones charges walk
+    pub subscriptions_by_account: LookupMap<AccountId, Vec<u64>>,
+    pub next_subscription_id: u64,
+    pub streams: LookupMap<u64, Stream>,
+    pub next_stream_id: u64,
+    pub streams_by_account: LookupMap<AccountId, Vec<u64>>, // by receiver
+    pub invoices: LookupMap<String, Invoice>,
+    pub next_invoice_id: u64,
+    pub invoices_by_account: LookupMap<AccountId, Vec<String>>, // by issuer
+    pub blocked_senders: LookupSet<AccountId>,
+    pub blocked_receivers: LookupSet<AccountId>,
+    pub allowlist_enabled: bool,
+    pub allowed_accounts: LookupSet<AccountId>,
+    pub attesters: LookupSet<AccountId>,
+    pub tier_limits: LookupMap<u8, TierLimit>,
+    pub sender_velocity_limits: LookupMap<AccountId, VelocityLimit>,
+    pub campaign_velocity_limits: LookupMap<String, VelocityLimit>,
+    pub sender_velocity: LookupMap<AccountId, VelocityTracker>,
+    pub campaign_velocity: LookupMap<String, VelocityTracker>,
+    pub retention_period: u64, // epochs
+    pub auto_register_enabled: bool,
+    pub totals: AccountingTotals,
+    pub campaign_stats: LookupMap<String, CampaignStats>,
+    pub account_ids: UnorderedSet<AccountId>,
+    pub dashboard: DashboardCounters,
+    pub open_transactions_by_sender: LookupMap<AccountId, u64>,
+    pub claimed_by_epoch: LookupMap<u64, (u64, Balance)>, // epoch % DASHBOARD_EPOCHS: (epoch, claimed)
+    pub fee_policy: FeePolicy,
+    pub referrers: LookupMap<AccountId, AccountId>,
+    pub referrals: LookupMap<AccountId, Vec<AccountId>>,
+    pub next_referral_id: u64,
+    pub organizations: LookupMap<String, Organization>,
+    pub state_version: u32,
 }
 
 #[near_bindgen]
@@ -37,16 +136,94 @@ impl HurdlePayment {
         HurdlePayment {
             owner_id: env::current_account_id(),
             accounts: LookupMap::new(StorageKey::AccountKey),
+            prepaid_balances: LookupMap::new(StorageKey::PrepaidBalance),
+            voucher_keys: LookupMap::new(StorageKey::VoucherKey),
+            consumed_nonces: LookupSet::new(StorageKey::ConsumedNonce),
+            campaigns: LookupMap::new(StorageKey::CampaignKey),
+            subscriptions: LookupMap::new(StorageKey::SubscriptionKey),
+            open_subscriptions: UnorderedSet::new(StorageKey::OpenSubscriptions),
+            subscriptions_by_account: LookupMap::new(StorageKey::SubscriptionsByAccount),
+            next_subscription_id: 0,
+            streams: LookupMap::new(StorageKey::StreamKey),
+            next_stream_id: 0,
+            streams_by_account: LookupMap::new(StorageKey::StreamsByAccount),
+            invoices: LookupMap::new(StorageKey::InvoiceKey),
+            next_invoice_id: 0,
+            invoices_by_account: LookupMap::new(StorageKey::InvoicesByAccount),
+            blocked_senders: LookupSet::new(StorageKey::BlockedSender),
+            blocked_receivers: LookupSet::new(StorageKey::BlockedReceiver),
+            allowlist_enabled: false,
+            allowed_accounts: LookupSet::new(StorageKey::AllowedAccount),
+            attesters: LookupSet::new(StorageKey::Attester),
+            tier_limits: LookupMap::new(StorageKey::TierLimit),
+            sender_velocity_limits: LookupMap::new(StorageKey::SenderVelocityLimit),
+            campaign_velocity_limits: LookupMap::new(StorageKey::CampaignVelocityLimit),
+            sender_velocity: LookupMap::new(StorageKey::SenderVelocity),
+            campaign_velocity: LookupMap::new(StorageKey::CampaignVelocity),
+            retention_period: 60,
+            auto_register_enabled: false,
+            totals: AccountingTotals::default(),
+            campaign_stats: LookupMap::new(StorageKey::CampaignStats),
+            account_ids: UnorderedSet::new(StorageKey::AccountIds),
+            dashboard: DashboardCounters::default(),
+            open_transactions_by_sender: LookupMap::new(StorageKey::OpenTransactionsBySender),
+            claimed_by_epoch: LookupMap::new(StorageKey::ClaimedByEpoch),
+            fee_policy: FeePolicy::default(),
+            referrers: LookupMap::new(StorageKey::Referrer),
+            referrals: LookupMap::new(StorageKey::Referrals),
+            next_referral_id: 0,
+            organizations: LookupMap::new(StorageKey::Organization),
+            state_version: STATE_VERSION,
         }
     }
 
+    // Upgrades the version 0 state in place. Its accounts follow with `migrate_accounts` since the
+    // old `accounts` map can't be iterated
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only the contract can migrate its state"
+        );
+        let state = env::storage_read(b"STATE");
+        assert!(state.is_some(), "No contract state to migrate");
+        let state = state.unwrap();
+        let state_version = stored_state_version(&state);
+        assert_eq!(
+            state_version,
+            Some(0),
+            "Only version 0 state can be migrated"
+        );
+        let old_state = OldHurdlePayment::try_from_slice(&state).unwrap();
+        let mut contract = Self::new();
+        contract.owner_id = old_state.owner_id;
+        contract.state_version = state_version.unwrap() + 1;
+        contract
+    }
+
+    // Rewrites the given accounts that are still in the version 0 layout, returns how many
+    #[payable]
+    pub fn migrate_accounts(&mut self, account_ids: Vec<AccountId>) -> u64 {
+        assert_at_least_one_yocto();
+        self.internal_assert_owner();
+        let before_storage_usage = env::storage_usage();
+        let migrated = account_ids
+            .iter()
+            .filter(|account_id| self.internal_migrate_account(account_id))
+            .count();
+        refund_deposit(0, env::storage_usage().saturating_sub(before_storage_usage));
+        migrated as u64
+    }
+
     #[payable]
-    pub fn register_new_account(&mut self, account_id: AccountId) {
+    pub fn register_new_account(&mut self, account_id: AccountId, referrer_id: Option<AccountId>) {
         assert_at_least_one_yocto();
         assert!(
             env::is_valid_account_id(account_id.as_bytes()),
             "Invalid account id"
         );
+        self.internal_screen_receiver(&account_id);
         let account_stake = self.accounts.get(&account_id);
 
         if account_stake.is_some() {
@@ -57,6 +234,15 @@ impl HurdlePayment {
             let before_storage_usage = env::storage_usage();
             // Refund deposited token to user's account
             self.internal_register_account(account_id.clone());
+            if let Some(referrer_id) = referrer_id {
+                // Whoever pays for someone else's registration does not get to pick the referrer
+                assert_eq!(
+                    env::predecessor_account_id(),
+                    account_id,
+                    "Only the account itself can name its referrer"
+                );
+                self.internal_register_referrer(&account_id, referrer_id);
+            }
             let after_storage_usage = env::storage_usage();
             refund_deposit(0, after_storage_usage - before_storage_usage);
         }
@@ -64,6 +250,8 @@ impl HurdlePayment {
 
     #[payable]
     pub fn send_to_receiver(&mut self, receiver_id: AccountId, y_amount: f64) {
+        self.internal_screen_sender(&env::predecessor_account_id());
+        self.internal_screen_receiver(&receiver_id);
         let attached_amount = env::attached_deposit();
         let amount = (y_amount * 1_000_000_000_000_000_000_000_000_f64) as u128;
         if attached_amount >= amount {
@@ -75,6 +263,7 @@ impl HurdlePayment {
     }
 
     #[payable]
+    #[allow(clippy::too_many_arguments)]
     pub fn send_to_contract(
         &mut self,
         receiver_id: AccountId,
@@ -82,31 +271,422 @@ impl HurdlePayment {
         cash_hold_time: i64,
         campaign_id: String,
         transaction_id: String,
+        refund_window: Option<u64>,
+        schedule: Option<PayoutSchedule>,
     ) {
+        assert_user_transaction_id(&transaction_id);
         let amount = (y_amount * 1_000_000_000_000_000_000_000_000_f64) as u128;
         let before_storage_usage = env::storage_usage();
+        if self.auto_register_enabled && self.accounts.get(&receiver_id).is_none() {
+            // The registration storage is paid from the sender's attached deposit
+            assert!(
+                env::is_valid_account_id(receiver_id.as_bytes()),
+                "Invalid account id"
+            );
+            self.internal_register_account(receiver_id.clone());
+        }
         // Refund deposited token to user's account
-        self.internal_create_transfer_transaction(
+        let locked = self.internal_create_transfer_transaction(
+            env::predecessor_account_id(),
             receiver_id,
             amount,
             cash_hold_time * 2,
+            refund_window.map(|refund_window| refund_window.saturating_mul(2)),
+            schedule.map_or(PayoutSchedule::Lump, PayoutSchedule::in_epochs),
             campaign_id,
             transaction_id,
+            None,
         );
+        // Rejected payments return the deposit without panicking so the rejection event is kept
+        let amount = locked.map_or(0, |platform_fee| amount + platform_fee);
         let after_storage_usage = env::storage_usage();
-        if after_storage_usage > before_storage_usage {
-            refund_deposit(
-                amount,
-                after_storage_usage
-                    .checked_sub(before_storage_usage)
-                    .unwrap(),
+        refund_deposit(
+            amount,
+            after_storage_usage
+                .checked_sub(before_storage_usage)
+                .unwrap(),
+        );
+    }
+
+    #[payable]
+    pub fn create_campaign(
+        &mut self,
+        campaign_id: String,
+        refund_window: Option<u64>,
+        organization_id: Option<String>,
+    ) {
+        assert_at_least_one_yocto();
+        assert!(
+            self.campaigns.get(&campaign_id).is_none(),
+            "Campaign already exists"
+        );
+        if let Some(organization_id) = &organization_id {
+            self.internal_assert_organization_role(organization_id, &["ADMIN", "SPENDER"]);
+        }
+        let before_storage_usage = env::storage_usage();
+        let campaign = Campaign {
+            owner_id: env::predecessor_account_id(),
+            refund_window: refund_window.map(|refund_window| refund_window.saturating_mul(2)),
+            approval_policy: None,
+            organization_id,
+        };
+        self.campaigns.insert(&campaign_id, &campaign);
+        HurdleEvent::CampaignCreated(vec![CampaignCreatedData {
+            campaign_id,
+            owner_id: campaign.owner_id,
+            refund_window: campaign.refund_window,
+        }])
+        .emit();
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(0, after_storage_usage - before_storage_usage);
+    }
+
+    #[payable]
+    pub fn set_campaign_refund_window(&mut self, campaign_id: String, refund_window: Option<u64>) {
+        assert_one_yocto();
+        let mut campaign = self.internal_get_campaign_as_owner(&campaign_id);
+        campaign.refund_window = refund_window.map(|refund_window| refund_window.saturating_mul(2));
+        self.campaigns.insert(&campaign_id, &campaign);
+    }
+
+    // Applies to the campaign's payments created from now on, a `None` policy removes it
+    #[payable]
+    pub fn set_campaign_approval_policy(
+        &mut self,
+        campaign_id: String,
+        policy: Option<ApprovalPolicyJson>,
+    ) {
+        assert_one_yocto();
+        let mut campaign = self.internal_get_campaign_as_owner(&campaign_id);
+        campaign.approval_policy = policy.map(ApprovalPolicy::from);
+        if let (Some(policy), Some(organization_id)) =
+            (&campaign.approval_policy, &campaign.organization_id)
+        {
+            let organization = self.organizations.get(organization_id).unwrap();
+            assert!(
+                policy
+                    .approvers
+                    .iter()
+                    .all(|approver_id| organization.role(approver_id).is_some()),
+                "Approvers must be members of the campaign's organization"
             );
         }
+        self.campaigns.insert(&campaign_id, &campaign);
+    }
+
+    #[payable]
+    pub fn approve_transaction(
+        &mut self,
+        receiver_id: AccountId,
+        create_epoch: u64,
+        transaction_id: String,
+    ) {
+        assert_one_yocto();
+        self.internal_record_approval(receiver_id, create_epoch, transaction_id, true);
+    }
+
+    // Once approval can no longer be reached the payment goes back to its sender
+    #[payable]
+    pub fn reject_transaction(
+        &mut self,
+        receiver_id: AccountId,
+        create_epoch: u64,
+        transaction_id: String,
+    ) {
+        assert_one_yocto();
+        self.internal_record_approval(receiver_id, create_epoch, transaction_id, false);
     }
 
+    // Anyone can trigger the claim, the caller pays for the claim records it adds
     #[payable]
     pub fn claim_and_withdraw(&mut self, account_id: AccountId) {
+        let before_storage_usage = env::storage_usage();
         self.internal_unlock_locked_balance(account_id);
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(0, after_storage_usage.saturating_sub(before_storage_usage));
+    }
+
+    // Called by the receiver contract itself to get notified of its claims
+    #[payable]
+    pub fn set_claim_hook_enabled(&mut self, enabled: bool) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let account = self.accounts.get(&account_id);
+        assert!(account.is_some(), "Account not found");
+        let mut account = account.unwrap();
+        account.claim_hook_enabled = enabled;
+        self.accounts.insert(&account_id, &account);
+    }
+
+    #[private]
+    pub fn on_claim_payout_result(
+        &mut self,
+        account_id: AccountId,
+        claimed_at: u64,
+        claims: Vec<PendingClaim>,
+    ) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            self.internal_settle_claims(&account_id, claims);
+            return true;
+        }
+        self.internal_revert_claims(account_id, claimed_at, claims);
+        false
+    }
+
+    #[payable]
+    pub fn close_account(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let released_storage = self.internal_close_account(&account_id);
+        if released_storage > 0 {
+            Promise::new(account_id)
+                .transfer(env::storage_byte_cost() * Balance::from(released_storage));
+        }
+    }
+
+    pub fn prune_settled_epochs(&mut self, account_id: AccountId) {
+        self.internal_prune_settled_epochs(&account_id);
+    }
+
+    #[payable]
+    pub fn set_auto_register_enabled(&mut self, enabled: bool) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        self.auto_register_enabled = enabled;
+    }
+
+    #[payable]
+    pub fn set_retention_period(&mut self, retention_period: u64) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        self.retention_period = retention_period * 2;
+    }
+
+    // The creator becomes the organization's first admin
+    #[payable]
+    pub fn create_organization(&mut self, organization_id: String) {
+        assert_at_least_one_yocto();
+        assert!(
+            self.organizations.get(&organization_id).is_none(),
+            "Organization already exists"
+        );
+        let before_storage_usage = env::storage_usage();
+        let organization = Organization {
+            members: vec![(env::predecessor_account_id(), "ADMIN".to_string())],
+            prepaid_balance: 0,
+        };
+        self.organizations.insert(&organization_id, &organization);
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(0, after_storage_usage - before_storage_usage);
+    }
+
+    // A `None` role removes the member
+    #[payable]
+    pub fn set_organization_member(
+        &mut self,
+        organization_id: String,
+        account_id: AccountId,
+        role: Option<String>,
+    ) {
+        assert_at_least_one_yocto();
+        let before_storage_usage = env::storage_usage();
+        self.internal_set_organization_member(&organization_id, account_id, role);
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(0, after_storage_usage.saturating_sub(before_storage_usage));
+    }
+
+    // Anyone can fund an organization, the whole attached deposit is credited
+    #[payable]
+    pub fn deposit_organization_balance(&mut self, organization_id: String) {
+        let organization = self.organizations.get(&organization_id);
+        assert!(organization.is_some(), "Organization not found");
+        let mut organization = organization.unwrap();
+        organization.prepaid_balance += env::attached_deposit();
+        self.organizations.insert(&organization_id, &organization);
+        self.totals.total_prepaid += env::attached_deposit();
+    }
+
+    #[payable]
+    pub fn withdraw_organization_balance(&mut self, organization_id: String, amount: U128) {
+        assert_one_yocto();
+        let mut organization = self.internal_assert_organization_role(&organization_id, &["ADMIN"]);
+        assert!(
+            organization.prepaid_balance >= amount.0,
+            "Not enough balance in organization {}",
+            organization_id
+        );
+        organization.prepaid_balance -= amount.0;
+        self.organizations.insert(&organization_id, &organization);
+        self.totals.total_prepaid -= amount.0;
+        Promise::new(env::predecessor_account_id()).transfer(amount.0);
+    }
+
+    // Paid from the organization's balance, the calling member is recorded as the sender
+    #[payable]
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_from_organization(
+        &mut self,
+        organization_id: String,
+        receiver_id: AccountId,
+        amount: U128,
+        cash_hold_time: i64,
+        campaign_id: String,
+        transaction_id: String,
+        refund_window: Option<u64>,
+        schedule: Option<PayoutSchedule>,
+    ) {
+        assert_one_yocto();
+        self.internal_send_from_organization(
+            organization_id,
+            receiver_id,
+            amount.0,
+            cash_hold_time * 2,
+            refund_window.map(|refund_window| refund_window.saturating_mul(2)),
+            schedule.map_or(PayoutSchedule::Lump, PayoutSchedule::in_epochs),
+            campaign_id,
+            transaction_id,
+        );
+    }
+
+    #[payable]
+    pub fn set_platform_fee(&mut self, platform_fee_bps: u16) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        assert!(
+            platform_fee_bps <= MAX_PLATFORM_FEE_BPS,
+            "Platform fee cannot exceed {} bps",
+            MAX_PLATFORM_FEE_BPS
+        );
+        self.fee_policy.platform_fee_bps = platform_fee_bps;
+    }
+
+    // Each level takes its share of the platform fee, the direct referrer comes first
+    #[payable]
+    pub fn set_referral_policy(&mut self, referral_level_bps: Vec<u16>, cash_hold_time: u64) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        assert!(
+            referral_level_bps.len() <= MAX_REFERRAL_LEVELS,
+            "At most {} referral levels",
+            MAX_REFERRAL_LEVELS
+        );
+        let total_bps: u64 = referral_level_bps.iter().map(|bps| *bps as u64).sum();
+        assert!(
+            total_bps <= 10_000,
+            "Referral shares cannot exceed 10000 bps of the fee"
+        );
+        self.fee_policy.referral_level_bps = referral_level_bps;
+        self.fee_policy.referral_cash_hold_time = cash_hold_time * 2;
+    }
+
+    #[payable]
+    pub fn withdraw_fees(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        assert!(
+            amount.0 <= self.totals.total_fees,
+            "Only {} yoctoNEAR of fees collected",
+            self.totals.total_fees
+        );
+        self.totals.total_fees -= amount.0;
+        Promise::new(self.owner_id.clone()).transfer(amount.0);
+    }
+
+    #[payable]
+    pub fn release_milestone(
+        &mut self,
+        receiver_id: AccountId,
+        create_epoch: u64,
+        transaction_id: String,
+        milestone_index: u64,
+    ) {
+        assert_one_yocto();
+        let (_, milestone) = self.internal_get_milestone(
+            &receiver_id,
+            create_epoch,
+            &transaction_id,
+            milestone_index,
+        );
+        assert!(
+            !milestone.oracle && env::predecessor_account_id() == milestone.approver_id,
+            "Only the milestone's approver can release it"
+        );
+        self.internal_release_milestone(receiver_id, create_epoch, transaction_id, milestone_index);
+    }
+
+    // Anyone can ask the oracle, the milestone is released when it answers true
+    pub fn check_milestone_oracle(
+        &mut self,
+        receiver_id: AccountId,
+        create_epoch: u64,
+        transaction_id: String,
+        milestone_index: u64,
+    ) -> Promise {
+        let (_, milestone) = self.internal_get_milestone(
+            &receiver_id,
+            create_epoch,
+            &transaction_id,
+            milestone_index,
+        );
+        assert!(milestone.oracle, "Milestone is not released by an oracle");
+        assert!(
+            milestone.released_at.is_none() && milestone.reclaimed_at.is_none(),
+            "Milestone already settled"
+        );
+        ext_milestone_oracle::is_milestone_met(
+            receiver_id.clone(),
+            transaction_id.clone(),
+            milestone_index,
+            &milestone.approver_id,
+            0,
+            MILESTONE_ORACLE_GAS,
+        )
+        .then(ext_self::on_milestone_oracle_result(
+            receiver_id,
+            create_epoch,
+            transaction_id,
+            milestone_index,
+            &env::current_account_id(),
+            0,
+            MILESTONE_CALLBACK_GAS,
+        ))
+    }
+
+    #[private]
+    pub fn on_milestone_oracle_result(
+        &mut self,
+        receiver_id: AccountId,
+        create_epoch: u64,
+        transaction_id: String,
+        milestone_index: u64,
+    ) -> bool {
+        let met = match env::promise_result(0) {
+            PromiseResult::Successful(result) => {
+                near_sdk::serde_json::from_slice::<bool>(&result).unwrap_or(false)
+            }
+            _ => false,
+        };
+        // The sender may have reclaimed the milestone while the oracle was answering
+        let (_, milestone) = self.internal_get_milestone(
+            &receiver_id,
+            create_epoch,
+            &transaction_id,
+            milestone_index,
+        );
+        if !met || milestone.released_at.is_some() || milestone.reclaimed_at.is_some() {
+            return false;
+        }
+        self.internal_release_milestone(receiver_id, create_epoch, transaction_id, milestone_index);
+        true
+    }
+
+    pub fn reclaim_milestones(
+        &mut self,
+        receiver_id: AccountId,
+        create_epoch: u64,
+        transaction_id: String,
+    ) -> U128 {
+        U128(self.internal_reclaim_milestones(receiver_id, create_epoch, transaction_id))
     }
 
     #[payable]
@@ -135,275 +715,3157 @@ impl HurdlePayment {
         self.internal_refund_by_epoch(sender_id, receiver_id, cash_hold_time);
     }
 
-    pub fn get_account_info(&self, account_id: AccountId) -> AccountJson {
+    #[payable]
+    pub fn deposit_prepaid_balance(&mut self) {
+        assert_at_least_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let before_storage_usage = env::storage_usage();
+        self.internal_deposit_prepaid_balance(&account_id, 0);
+        let after_storage_usage = env::storage_usage();
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(after_storage_usage - before_storage_usage);
         assert!(
-            env::is_valid_account_id(account_id.as_bytes()),
-            "Invalid account id"
+            env::attached_deposit() > storage_cost,
+            "Must attach more than {} yoctoNear to cover storage",
+            storage_cost
         );
-        let account = self.accounts.get(&account_id).unwrap();
-        AccountJson::from(account_id, account)
+        self.internal_deposit_prepaid_balance(&account_id, env::attached_deposit() - storage_cost);
     }
 
-    pub fn get_transactions_info(
-        &self,
-        account_id: AccountId,
-        start_epoch: u64,
-        end_epoch: u64,
-    ) -> Vec<TransferTransactionJson> {
-        let account = self.accounts.get(&account_id).unwrap();
-        let mut vec = Vec::new();
-        let mut start_epoch_parse = start_epoch;
-        while start_epoch_parse <= end_epoch {
-            let transactions = account.transactions.get(&start_epoch_parse);
-            if transactions.is_some() {
-                let transactions = transactions.unwrap();
-                for transaction in transactions.to_vec() {
-                    let (transaction_id, transaction) = transaction;
-                    vec.push(TransferTransactionJson::from(transaction_id, transaction));
-                }
+    #[payable]
+    pub fn withdraw_prepaid_balance(&mut self, amount: U128) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_withdraw_prepaid_balance(&account_id, amount.0);
+        Promise::new(account_id).transfer(amount.0);
+    }
+
+    #[payable]
+    pub fn register_voucher_key(&mut self, public_key: Base58PublicKey) {
+        assert_at_least_one_yocto();
+        assert!(
+            public_key.0.len() == 33 && public_key.0[0] == 0,
+            "Voucher key must be an ed25519 public key"
+        );
+        let before_storage_usage = env::storage_usage();
+        self.voucher_keys
+            .insert(&env::predecessor_account_id(), &public_key.0[1..].to_vec());
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(0, after_storage_usage.saturating_sub(before_storage_usage));
+    }
+
+    pub fn redeem_voucher(&mut self, voucher: Voucher, signature: Base64VecU8) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            voucher.receiver_id,
+            "Only the receiver can redeem this voucher"
+        );
+        self.internal_redeem_voucher(voucher, signature.0);
+    }
+
+    // A `max_periods` of 0 keeps charging until the sender cancels
+    #[payable]
+    pub fn create_subscription(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        period: u64,
+        cash_hold_time: u64,
+        max_periods: u64,
+        campaign_id: String,
+    ) -> u64 {
+        assert_at_least_one_yocto();
+        let before_storage_usage = env::storage_usage();
+        let subscription_id = self.internal_create_subscription(
+            env::predecessor_account_id(),
+            receiver_id,
+            campaign_id,
+            amount.0,
+            period * 2,
+            cash_hold_time * 2,
+            max_periods,
+        );
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(0, after_storage_usage - before_storage_usage);
+        subscription_id
+    }
+
+    // Pages over the open subscriptions, ones that cannot be charged are skipped with a log.
+    // Settled subscriptions leave the set, so a later page may start one subscription further
+    pub fn charge_due_subscriptions(&mut self, from_index: u64, limit: u64) -> u64 {
+        let open_subscriptions = self.open_subscriptions.as_vector();
+        let end_index = std::cmp::min(from_index.saturating_add(limit), open_subscriptions.len());
+        let subscription_ids: Vec<u64> = (from_index..end_index)
+            .filter_map(|index| open_subscriptions.get(index))
+            .collect();
+        let mut charged = 0;
+        for subscription_id in subscription_ids {
+            let mut subscription = self.subscriptions.get(&subscription_id).unwrap();
+            let charged_periods =
+                self.internal_charge_subscription(subscription_id, &mut subscription);
+            if charged_periods > 0 {
+                self.subscriptions.insert(&subscription_id, &subscription);
+                charged += charged_periods;
+            }
+            if subscription.status == "COMPLETED" {
+                self.open_subscriptions.remove(&subscription_id);
             }
-            start_epoch_parse += 1;
         }
-        vec
+        charged
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, MockedBlockchain};
+    #[payable]
+    pub fn pause_subscription(&mut self, subscription_id: u64) {
+        assert_one_yocto();
+        self.internal_set_subscription_status(subscription_id, "PAUSED");
+    }
+
+    #[payable]
+    pub fn resume_subscription(&mut self, subscription_id: u64) {
+        assert_one_yocto();
+        self.internal_set_subscription_status(subscription_id, "ACTIVE");
+    }
+
+    #[payable]
+    pub fn cancel_subscription(&mut self, subscription_id: u64) {
+        assert_one_yocto();
+        self.internal_set_subscription_status(subscription_id, "CANCELLED");
+    }
+
+    pub fn get_subscriptions(&self, account_id: AccountId) -> Vec<SubscriptionJson> {
+        self.subscriptions_by_account
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|subscription_id| {
+                SubscriptionJson::from(
+                    subscription_id,
+                    self.subscriptions.get(&subscription_id).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[payable]
+    pub fn create_stream(
+        &mut self,
+        receiver_id: AccountId,
+        rate_per_second: U128,
+        start_at: Option<u64>,
+    ) -> u64 {
+        self.internal_screen_sender(&env::predecessor_account_id());
+        self.internal_screen_receiver(&receiver_id);
+        assert!(
+            self.accounts.get(&receiver_id).is_some(),
+            "Account not found"
+        );
+        assert!(rate_per_second.0 > 0, "Stream rate must be positive");
+        let before_storage_usage = env::storage_usage();
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 1;
+        let mut stream_ids = self
+            .streams_by_account
+            .get(&receiver_id)
+            .unwrap_or_default();
+        stream_ids.push(stream_id);
+        self.streams_by_account.insert(&receiver_id, &stream_ids);
+        let mut stream = Stream {
+            sender_id: env::predecessor_account_id(),
+            receiver_id,
+            rate_per_second: rate_per_second.0,
+            deposit: 0,
+            withdrawn: 0,
+            started_at: std::cmp::max(start_at.unwrap_or(0), now_in_seconds()),
+            stopped_at: None,
+            status: "ACTIVE".to_string(),
+        };
+        self.streams.insert(&stream_id, &stream);
+        let after_storage_usage = env::storage_usage();
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(after_storage_usage - before_storage_usage);
+        assert!(
+            env::attached_deposit() > storage_cost,
+            "Must attach more than {} yoctoNear to cover storage",
+            storage_cost
+        );
+        stream.deposit = env::attached_deposit() - storage_cost;
+        self.totals.total_streaming += stream.deposit;
+        self.streams.insert(&stream_id, &stream);
+        stream_id
+    }
+
+    pub fn withdraw_from_stream(&mut self, stream_id: u64) -> U128 {
+        U128(self.internal_withdraw_from_stream(stream_id))
+    }
+
+    #[payable]
+    pub fn stop_stream(&mut self, stream_id: u64) -> U128 {
+        assert_one_yocto();
+        U128(self.internal_stop_stream(stream_id))
+    }
+
+    pub fn get_stream(&self, stream_id: u64) -> StreamJson {
+        StreamJson::from(stream_id, self.internal_get_stream(stream_id))
+    }
+
+    #[payable]
+    pub fn create_invoice(
+        &mut self,
+        amount: U128,
+        campaign_id: String,
+        cash_hold_time: i64,
+        due_at: u64,
+        memo: String,
+        payer_id: Option<AccountId>,
+    ) -> String {
+        assert_at_least_one_yocto();
+        let issuer_id = env::predecessor_account_id();
+        assert!(self.accounts.get(&issuer_id).is_some(), "Account not found");
+        let before_storage_usage = env::storage_usage();
+        let invoice_id = format!("invoice-{}", self.next_invoice_id);
+        self.next_invoice_id += 1;
+        let mut invoice_ids = self.invoices_by_account.get(&issuer_id).unwrap_or_default();
+        invoice_ids.push(invoice_id.clone());
+        self.invoices_by_account.insert(&issuer_id, &invoice_ids);
+        let invoice = Invoice {
+            issuer_id,
+            payer_id,
+            campaign_id,
+            amount: amount.0,
+            cash_hold_time,
+            memo,
+            created_at: env::epoch_height(),
+            due_at,
+            status: "OPEN".to_string(),
+        };
+        self.invoices.insert(&invoice_id, &invoice);
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(0, after_storage_usage - before_storage_usage);
+        invoice_id
+    }
+
+    #[payable]
+    pub fn pay_invoice(&mut self, invoice_id: String) {
+        let before_storage_usage = env::storage_usage();
+        let amount = self.internal_pay_invoice(invoice_id);
+        let after_storage_usage = env::storage_usage();
+        refund_deposit(amount, after_storage_usage - before_storage_usage);
+    }
+
+    #[payable]
+    pub fn cancel_invoice(&mut self, invoice_id: String) {
+        assert_one_yocto();
+        self.internal_cancel_invoice(invoice_id);
+    }
+
+    pub fn get_invoice(&self, invoice_id: String) -> InvoiceJson {
+        InvoiceJson::from(invoice_id.clone(), self.internal_get_invoice(&invoice_id))
+    }
+
+    #[payable]
+    pub fn set_blocked_sender(&mut self, account_id: AccountId, blocked: bool) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        if blocked {
+            self.blocked_senders.insert(&account_id);
+        } else {
+            self.blocked_senders.remove(&account_id);
+        }
+    }
+
+    #[payable]
+    pub fn set_blocked_receiver(&mut self, account_id: AccountId, blocked: bool) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        if blocked {
+            self.blocked_receivers.insert(&account_id);
+        } else {
+            self.blocked_receivers.remove(&account_id);
+        }
+    }
+
+    #[payable]
+    pub fn set_allowlist_enabled(&mut self, enabled: bool) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        self.allowlist_enabled = enabled;
+    }
+
+    #[payable]
+    pub fn set_allowed_account(&mut self, account_id: AccountId, allowed: bool) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        if allowed {
+            self.allowed_accounts.insert(&account_id);
+        } else {
+            self.allowed_accounts.remove(&account_id);
+        }
+    }
+
+    #[payable]
+    pub fn set_attester(&mut self, account_id: AccountId, enabled: bool) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        if enabled {
+            self.attesters.insert(&account_id);
+        } else {
+            self.attesters.remove(&account_id);
+        }
+    }
+
+    #[payable]
+    pub fn set_tier_limit(&mut self, kyc_tier: u8, limit: TierLimitJson) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        self.tier_limits.insert(&kyc_tier, &TierLimit::from(limit));
+    }
+
+    #[payable]
+    pub fn attest_kyc_tier(&mut self, account_id: AccountId, kyc_tier: u8) {
+        assert_one_yocto();
+        assert!(
+            self.attesters.contains(&env::predecessor_account_id()),
+            "Only an attester can record a KYC tier"
+        );
+        let account = self.accounts.get(&account_id);
+        assert!(account.is_some(), "Account not found");
+        let mut account = account.unwrap();
+        account.kyc_tier = kyc_tier;
+        self.accounts.insert(&account_id, &account);
+    }
+
+    #[payable]
+    pub fn set_sender_velocity_limit(
+        &mut self,
+        sender_id: AccountId,
+        limit: Option<VelocityLimitJson>,
+    ) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        match limit {
+            Some(limit) => {
+                self.sender_velocity_limits
+                    .insert(&sender_id, &VelocityLimit::from(limit));
+            }
+            None => {
+                self.sender_velocity_limits.remove(&sender_id);
+                self.sender_velocity.remove(&sender_id);
+            }
+        }
+    }
+
+    #[payable]
+    pub fn set_campaign_velocity_limit(
+        &mut self,
+        campaign_id: String,
+        limit: Option<VelocityLimitJson>,
+    ) {
+        assert_one_yocto();
+        self.internal_assert_owner();
+        match limit {
+            Some(limit) => {
+                self.campaign_velocity_limits
+                    .insert(&campaign_id, &VelocityLimit::from(limit));
+            }
+            None => {
+                self.campaign_velocity_limits.remove(&campaign_id);
+                self.campaign_velocity.remove(&campaign_id);
+            }
+        }
+    }
+
+    pub fn get_sender_velocity_limit(&self, sender_id: AccountId) -> Option<VelocityLimitJson> {
+        self.sender_velocity_limits
+            .get(&sender_id)
+            .map(VelocityLimitJson::from)
+    }
+
+    pub fn get_campaign_velocity_limit(&self, campaign_id: String) -> Option<VelocityLimitJson> {
+        self.campaign_velocity_limits
+            .get(&campaign_id)
+            .map(VelocityLimitJson::from)
+    }
+
+    pub fn get_tier_limit(&self, kyc_tier: u8) -> Option<TierLimitJson> {
+        self.tier_limits.get(&kyc_tier).map(TierLimitJson::from)
+    }
+
+    pub fn get_account_screening(&self, account_id: AccountId) -> AccountScreeningJson {
+        AccountScreeningJson {
+            blocked_sender: self.blocked_senders.contains(&account_id),
+            blocked_receiver: self.blocked_receivers.contains(&account_id),
+            allowlisted: self.allowed_accounts.contains(&account_id),
+            account_id,
+        }
+    }
+
+    pub fn get_campaign_info(&self, campaign_id: String) -> CampaignJson {
+        let campaign = self.campaigns.get(&campaign_id);
+        assert!(campaign.is_some(), "Campaign not found");
+        CampaignJson::from(campaign_id, campaign.unwrap())
+    }
+
+    pub fn get_campaign_stats(&self, campaign_id: String) -> CampaignStatsJson {
+        let stats = self
+            .campaign_stats
+            .get(&campaign_id)
+            .unwrap_or_else(|| CampaignStats::new(&campaign_id));
+        CampaignStatsJson::from(campaign_id, &stats)
+    }
+
+    // The campaign's receivers ranked by the net amount locked for them, from_index counts from
+    // the top
+    pub fn get_top_receivers(
+        &self,
+        campaign_id: String,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<CampaignReceiverJson> {
+        let stats = match self.campaign_stats.get(&campaign_id) {
+            Some(stats) => stats,
+            None => return Vec::new(),
+        };
+        stats
+            .ranking
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|((rank, account_id), _)| CampaignReceiverJson {
+                account_id,
+                amount: U128(Balance::MAX - rank),
+            })
+            .collect()
+    }
+
+    pub fn get_dashboard(&self, recent_epochs: u64) -> DashboardJson {
+        assert!(
+            recent_epochs <= DASHBOARD_EPOCHS,
+            "The dashboard keeps at most {} epochs",
+            DASHBOARD_EPOCHS
+        );
+        let current_epoch = env::epoch_height();
+        let value_claimed_recently = (0..recent_epochs.min(current_epoch + 1))
+            .map(|age| current_epoch - age)
+            .filter_map(|epoch| {
+                self.claimed_by_epoch
+                    .get(&(epoch % DASHBOARD_EPOCHS))
+                    .filter(|(claimed_at, _)| *claimed_at == epoch)
+                    .map(|(_, amount)| amount)
+            })
+            .sum();
+        DashboardJson {
+            registered_accounts: self.account_ids.len(),
+            active_senders: self.dashboard.active_senders,
+            locked_transactions: self.dashboard.locked_transactions,
+            claimed_transactions: self.dashboard.claimed_transactions,
+            refunded_transactions: self.dashboard.refunded_transactions,
+            value_locked: U128(self.totals.total_locked),
+            recent_epochs,
+            value_claimed_recently: U128(value_claimed_recently),
+        }
+    }
+
+    pub fn get_accounts(&self, from_index: u64, limit: u64) -> Vec<AccountJson> {
+        let account_ids = self.account_ids.as_vector();
+        let end_index = std::cmp::min(from_index.saturating_add(limit), account_ids.len());
+        (from_index..end_index)
+            .map(|index| {
+                let account_id = account_ids.get(index).unwrap();
+                let account = self.accounts.get(&account_id).unwrap();
+                AccountJson::from(account_id, account)
+            })
+            .collect()
+    }
+
+    pub fn get_prepaid_balance(&self, account_id: AccountId) -> U128 {
+        U128(self.prepaid_balances.get(&account_id).unwrap_or(0))
+    }
+
+    pub fn get_organization(&self, organization_id: String) -> OrganizationJson {
+        let organization = self.organizations.get(&organization_id);
+        assert!(organization.is_some(), "Organization not found");
+        OrganizationJson::from(organization_id, organization.unwrap())
+    }
+
+    pub fn get_fee_policy(&self) -> FeePolicyJson {
+        FeePolicyJson::from(&self.fee_policy)
+    }
+
+    pub fn get_referral_tree(&self, account_id: AccountId, depth: u64) -> ReferralNodeJson {
+        assert!(
+            depth <= MAX_REFERRAL_LEVELS as u64,
+            "Referral trees go at most {} levels deep",
+            MAX_REFERRAL_LEVELS
+        );
+        self.internal_referral_tree(account_id, depth)
+    }
+
+    pub fn get_reconciliation(&self) -> ReconciliationJson {
+        ReconciliationJson::from(&self.totals)
+    }
+
+    pub fn get_account_info(&self, account_id: AccountId) -> AccountJson {
+        assert!(
+            env::is_valid_account_id(account_id.as_bytes()),
+            "Invalid account id"
+        );
+        let account = self.accounts.get(&account_id).unwrap();
+        AccountJson::from(account_id, account)
+    }
+
+    pub fn get_transactions_info(
+        &self,
+        account_id: AccountId,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> Vec<TransferTransactionJson> {
+        let account = self.accounts.get(&account_id).unwrap();
+        let mut vec = Vec::new();
+        let mut start_epoch_parse = start_epoch;
+        while start_epoch_parse <= end_epoch {
+            let transactions = account.transactions.get(&start_epoch_parse);
+            if let Some(transactions) = transactions {
+                for transaction in transactions.to_vec() {
+                    let (transaction_id, transaction) = transaction;
+                    vec.push(TransferTransactionJson::from(transaction_id, transaction));
+                }
+            }
+            start_epoch_parse += 1;
+        }
+        vec
+    }
+
+    // A page reads the transactions created in `limit` epochs from start_epoch, merging the pages
+    // from epoch 0 until `next_epoch` is `None` gives the whole statement
+    pub fn get_statement(
+        &self,
+        account_id: AccountId,
+        from_epoch: u64,
+        to_epoch: u64,
+        start_epoch: u64,
+        limit: u64,
+    ) -> StatementJson {
+        assert!(from_epoch <= to_epoch, "Invalid statement period");
+        let account = self.accounts.get(&account_id);
+        assert!(account.is_some(), "Account not found");
+        StatementJson::from(
+            account_id,
+            &account.unwrap(),
+            from_epoch,
+            to_epoch,
+            start_epoch,
+            limit,
+        )
+    }
+}
+
+#[cfg(test)]
+mod fuzz;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::json_types::ValidAccountId;
+    use near_sdk::serde::de::IgnoredAny;
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    fn get_context(is_view: bool) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .current_account_id(accounts(0))
+            .signer_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .is_view(is_view);
+        builder
+    }
+    // Mirrors the mocked receipt, whose fields are private
+    #[derive(Deserialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct MockedReceipt {
+        receiver_id: AccountId,
+        actions: Vec<MockedAction>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(crate = "near_sdk::serde")]
+    enum MockedAction {
+        CreateAccount,
+        DeployContract(IgnoredAny),
+        FunctionCall(MockedFunctionCall),
+        Transfer(MockedDeposit),
+        Stake(IgnoredAny),
+        AddKeyWithFullAccess(IgnoredAny),
+        AddKeyWithFunctionCall(IgnoredAny),
+        DeleteKey(IgnoredAny),
+        DeleteAccount(IgnoredAny),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct MockedDeposit {
+        deposit: Balance,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct MockedFunctionCall {
+        method_name: String,
+        args: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct ClaimPayoutArgs {
+        account_id: AccountId,
+        claimed_at: u64,
+        claims: Vec<PendingClaim>,
+    }
+
+    fn created_actions() -> Vec<(AccountId, MockedAction)> {
+        get_created_receipts()
+            .iter()
+            .map(|receipt| {
+                let receipt = near_sdk::serde_json::to_string(receipt).unwrap();
+                near_sdk::serde_json::from_str::<MockedReceipt>(&receipt).unwrap()
+            })
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .map(move |action| (receiver_id.clone(), action))
+            })
+            .collect()
+    }
+
+    // Every plain transfer the contract made since the last `testing_env!`
+    pub(crate) fn created_transfers() -> Vec<(AccountId, Balance)> {
+        created_actions()
+            .into_iter()
+            .filter_map(|(receiver_id, action)| match action {
+                MockedAction::Transfer(transfer) => Some((receiver_id, transfer.deposit)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Runs the payout callbacks scheduled since the last `testing_env!` as if every payout went
+    // through, then restores `context`
+    pub(crate) fn settle_claims(contract: &mut HurdlePayment, context: &VMContextBuilder) {
+        resolve_claims(contract, context, true);
+    }
+
+    // Same as `settle_claims`, with every payout either delivered or bounced
+    pub(crate) fn resolve_claims(
+        contract: &mut HurdlePayment,
+        context: &VMContextBuilder,
+        delivered: bool,
+    ) {
+        let callbacks: Vec<ClaimPayoutArgs> = created_actions()
+            .into_iter()
+            .filter_map(|(_, action)| match action {
+                MockedAction::FunctionCall(call)
+                    if call.method_name == "on_claim_payout_result" =>
+                {
+                    Some(near_sdk::serde_json::from_str(&call.args).unwrap())
+                }
+                _ => None,
+            })
+            .collect();
+        testing_env!(
+            context
+                .clone()
+                .storage_usage(env::storage_usage())
+                .predecessor_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![if delivered {
+                PromiseResult::Successful(vec![])
+            } else {
+                PromiseResult::Failed
+            }]
+        );
+        for callback in callbacks {
+            assert_eq!(
+                contract.on_claim_payout_result(
+                    callback.account_id,
+                    callback.claimed_at,
+                    callback.claims
+                ),
+                delivered
+            );
+        }
+        testing_env!(context.clone().storage_usage(env::storage_usage()).build());
+    }
+
+    // Checks the running totals against the accounts and transactions they summarize
+    // Organizations are not iterable, the accounting check only sees the ids tests create
+    pub(crate) const TEST_ORGANIZATIONS: [&str; 2] = ["org", "team"];
+
+    fn assert_accounting_invariants(contract: &HurdlePayment) {
+        let reconciliation = contract.get_reconciliation();
+        let mut total_locked = 0;
+        let mut total_prepaid = 0;
+        let mut locked_transactions = 0;
+        for index in 0..6 {
+            let account_id = accounts(index).to_string();
+            if let Some(account) = contract.accounts.get(&account_id) {
+                locked_transactions += account
+                    .transactions
+                    .values()
+                    .flat_map(|transactions| transactions.values().collect::<Vec<_>>())
+                    .filter(|transaction| transaction.status == "LOCK")
+                    .count() as u64;
+                let outstanding: Balance = account
+                    .transactions
+                    .values()
+                    .flat_map(|transactions| transactions.values().collect::<Vec<_>>())
+                    .filter(|transaction| transaction.status == "LOCK")
+                    .map(|transaction| transaction.outstanding_amount())
+                    .sum();
+                assert_eq!(outstanding, account.locked_balance);
+                total_locked += account.locked_balance;
+            }
+            total_prepaid += contract.prepaid_balances.get(&account_id).unwrap_or(0);
+        }
+        total_prepaid += TEST_ORGANIZATIONS
+            .iter()
+            .filter_map(|organization_id| contract.organizations.get(&organization_id.to_string()))
+            .map(|organization| organization.prepaid_balance)
+            .sum::<Balance>();
+        assert_eq!(reconciliation.total_locked.0, total_locked);
+        assert_eq!(reconciliation.total_prepaid.0, total_prepaid);
+        assert_eq!(contract.dashboard.locked_transactions, locked_transactions);
+        assert_eq!(
+            reconciliation.total_escrowed.0,
+            reconciliation.total_locked.0
+                + reconciliation.total_claimed.0
+                + reconciliation.total_refunded.0
+        );
+    }
+
+    #[test]
+    fn test_init_contract() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let contract = HurdlePayment::new();
+        assert_eq!(contract.owner_id, env::current_account_id());
+    }
+
+    #[test]
+    fn test_register_new_account() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(0).to_string(), None);
+        assert_eq!(
+            contract
+                .accounts
+                .get(&accounts(0).to_string())
+                .unwrap()
+                .total_revenue,
+            0
+        );
+    }
+
+    #[test]
+    fn test_send_to_contract() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "12".to_string(),
+            "test1".to_string(),
+            None,
+            None,
+        );
+        let account_info = contract.get_account_info(accounts(1).to_string());
+        assert_eq!(account_info.total_revenue, U128(999999999999999983222784));
+        assert_eq!(account_info.locked_balance, U128(999999999999999983222784));
+        let today = env::epoch_height();
+        assert_eq!(account_info.last_unlock_at, today);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "test2".to_string(),
+            None,
+            None,
+        );
+        let account_info = contract.get_account_info(accounts(1).to_string());
+        let transactions = contract.get_transactions_info(
+            accounts(1).to_string(),
+            env::epoch_height(),
+            env::epoch_height(),
+        );
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(
+            account_info.total_revenue,
+            U128(999999999999999983222784 * 2)
+        );
+
+        assert_eq!(
+            account_info.locked_balance,
+            U128(999999999999999983222784 * 2)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_unlock_balance() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "1".to_string(),
+            None,
+            None,
+        );
+
+        let locked_amount = contract
+            .accounts
+            .get(&accounts(1).to_string())
+            .unwrap()
+            .locked_balance;
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        let mut transactions = contract.get_transactions_info(
+            accounts(1).to_string(),
+            env::epoch_height(),
+            env::epoch_height(),
+        );
+
+        assert_eq!(transactions.pop().unwrap().status, "CLAIM");
+
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(0)
+        );
+
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .total_revenue,
+            U128(locked_amount)
+        );
+
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .total_revenue,
+            U128(locked_amount)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_refund_by_transaction_id() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.register_new_account(accounts(2).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            1,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+
+        contract.refund_by_transaction_id(
+            accounts(0).to_string(),
+            accounts(1).to_string(),
+            "test".to_string(),
+            env::epoch_height(),
+        );
+        let transaction = contract
+            .get_transactions_info(
+                accounts(1).to_string(),
+                env::epoch_height(),
+                env::epoch_height(),
+            )
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.claimable_at, env::epoch_height() + 2);
+        assert_eq!(&transaction.status, "REFUND");
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(0)
+        );
+
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .total_revenue,
+            U128(0)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_refund_by_epoch() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.register_new_account(accounts(2).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            1,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            1,
+            "1".to_string(),
+            "test1".to_string(),
+            None,
+            None,
+        );
+
+        contract.refund_by_epoch(accounts(0).to_string(), accounts(1).to_string(), 0);
+        let transaction = contract
+            .get_transactions_info(
+                accounts(1).to_string(),
+                env::epoch_height(),
+                env::epoch_height(),
+            )
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.claimable_at, env::epoch_height() + 2);
+        assert_eq!(&transaction.status, "REFUND");
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(0)
+        );
+
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .total_revenue,
+            U128(0)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    fn signed_voucher(nonce: u64, contract_id: &str) -> (Base58PublicKey, Voucher, Base64VecU8) {
+        use ed25519_dalek::{ExpandedSecretKey, PublicKey, SecretKey};
+        let secret_key = SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public_key = PublicKey::from(&secret_key);
+        let voucher = Voucher {
+            sender_id: accounts(0).to_string(),
+            receiver_id: accounts(1).to_string(),
+            amount: U128(1_000_000_000_000_000_000_000_000),
+            cash_hold_time: 0,
+            campaign_id: "1".to_string(),
+            transaction_id: format!("voucher{}", nonce),
+            nonce,
+            expires_at: env::epoch_height() + 10,
+        };
+        let signature = ExpandedSecretKey::from(&secret_key)
+            .sign(&voucher_message(contract_id, &voucher), &public_key)
+            .to_bytes();
+        let mut key = vec![0];
+        key.extend_from_slice(public_key.as_bytes());
+        (
+            Base58PublicKey(key),
+            voucher,
+            Base64VecU8(signature.to_vec()),
+        )
+    }
+
+    #[test]
+    fn test_redeem_voucher() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.deposit_prepaid_balance();
+        let (public_key, voucher, signature) = signed_voucher(1, accounts(0).as_ref());
+        contract.register_voucher_key(public_key);
+        let prepaid_balance = contract.get_prepaid_balance(accounts(0).to_string()).0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        contract.redeem_voucher(voucher, signature);
+
+        let account_info = contract.get_account_info(accounts(1).to_string());
+        assert_eq!(
+            account_info.locked_balance,
+            U128(1_000_000_000_000_000_000_000_000)
+        );
+        let transaction = contract
+            .get_transactions_info(
+                accounts(1).to_string(),
+                env::epoch_height(),
+                env::epoch_height(),
+            )
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.sender_id, accounts(0).to_string());
+        assert_eq!(&transaction.status, "LOCK");
+        assert!(
+            contract.get_prepaid_balance(accounts(0).to_string()).0
+                < prepaid_balance - 1_000_000_000_000_000_000_000_000
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voucher already redeemed")]
+    fn test_redeem_voucher_replay() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.deposit_prepaid_balance();
+        let (public_key, voucher, signature) = signed_voucher(1, accounts(0).as_ref());
+        contract.register_voucher_key(public_key);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        let (_, replayed_voucher, replayed_signature) = signed_voucher(1, accounts(0).as_ref());
+        contract.redeem_voucher(voucher, signature);
+        contract.redeem_voucher(replayed_voucher, replayed_signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid voucher signature")]
+    fn test_redeem_voucher_signed_for_other_contract() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.deposit_prepaid_balance();
+        let (public_key, voucher, signature) = signed_voucher(1, "other-deployment");
+        contract.register_voucher_key(public_key);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        contract.redeem_voucher(voucher, signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transaction ID referral-0 is reserved")]
+    fn test_send_with_reserved_transaction_id() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        // Taking the next referral id would make the referrer's commission fail to lock
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "referral-0".to_string(),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Refund window has closed")]
+    fn test_refund_after_refund_window() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_campaign("1".to_string(), Some(1), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            7,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+        let create_epoch = env::epoch_height();
+        let transaction = contract
+            .get_transactions_info(accounts(1).to_string(), create_epoch, create_epoch)
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.claimable_at, create_epoch + 14);
+        assert_eq!(transaction.refundable_until, create_epoch + 2);
+
+        testing_env!(context.epoch_height(create_epoch + 2).build());
+        contract.refund_by_transaction_id(
+            accounts(0).to_string(),
+            accounts(1).to_string(),
+            "test".to_string(),
+            create_epoch,
+        );
+    }
+
+    #[test]
+    fn test_campaign_refund_window_binds_only_owner() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        // The receiver squats the campaign id with a window that closes refunds immediately
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.create_campaign("1".to_string(), Some(0), None);
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            7,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+        let create_epoch = env::epoch_height();
+        let transaction = contract
+            .get_transactions_info(accounts(1).to_string(), create_epoch, create_epoch)
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.refundable_until, create_epoch + 14);
+    }
+
+    #[test]
+    fn test_unlock_linear_schedule() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            Some(PayoutSchedule::Linear {
+                cliff: 1,
+                duration: 4,
+            }),
+        );
+        let create_epoch = env::epoch_height();
+        let locked_amount = contract
+            .get_account_info(accounts(1).to_string())
+            .locked_balance
+            .0;
+
+        testing_env!(context.epoch_height(create_epoch + 1).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(locked_amount)
+        );
+
+        testing_env!(context.epoch_height(create_epoch + 4).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        let transaction = contract
+            .get_transactions_info(accounts(1).to_string(), create_epoch, create_epoch)
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.claimed_amount, U128(locked_amount / 2));
+        assert_eq!(&transaction.status, "LOCK");
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(locked_amount - locked_amount / 2)
+        );
+
+        testing_env!(context.epoch_height(create_epoch + 8).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        let transaction = contract
+            .get_transactions_info(accounts(1).to_string(), create_epoch, create_epoch)
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.claimed_amount, U128(locked_amount));
+        assert_eq!(&transaction.status, "CLAIM");
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(0)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_charge_due_subscriptions() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.deposit_prepaid_balance();
+        let subscription_id = contract.create_subscription(
+            accounts(1).to_string(),
+            U128(1_000_000_000_000_000_000_000_000),
+            1,
+            0,
+            2,
+            "1".to_string(),
+        );
+        let create_epoch = env::epoch_height();
+
+        assert_eq!(contract.charge_due_subscriptions(0, 10), 1);
+        assert_eq!(contract.charge_due_subscriptions(0, 10), 0);
+
+        testing_env!(context.epoch_height(create_epoch + 2).build());
+        assert_eq!(contract.charge_due_subscriptions(0, 10), 1);
+
+        testing_env!(context.epoch_height(create_epoch + 4).build());
+        assert_eq!(contract.charge_due_subscriptions(0, 10), 0);
+
+        let subscription = contract
+            .get_subscriptions(accounts(1).to_string())
+            .pop()
+            .unwrap();
+        assert_eq!(subscription.subscription_id, subscription_id);
+        assert_eq!(subscription.charged_periods, 2);
+        assert_eq!(&subscription.status, "COMPLETED");
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(2_000_000_000_000_000_000_000_000)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_charge_due_subscriptions_skips_failing_ones() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.register_new_account(accounts(2).to_string(), None);
+        contract.deposit_prepaid_balance();
+        for receiver in [accounts(1), accounts(2)] {
+            contract.create_subscription(
+                receiver.to_string(),
+                U128(100_000_000_000_000_000_000_000),
+                1,
+                0,
+                0,
+                "1".to_string(),
+            );
+        }
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_blocked_receiver(accounts(1).to_string(), true);
+
+        // Unlimited subscriptions catch up on a bounded number of missed periods per call
+        let create_epoch = env::epoch_height();
+        testing_env!(context.epoch_height(create_epoch + 100).build());
+        assert_eq!(
+            contract.charge_due_subscriptions(0, 10),
+            MAX_CATCH_UP_PERIODS
+        );
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains("Subscription 0 skipped")));
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(0)
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.cancel_subscription(1);
+        assert_eq!(contract.open_subscriptions.len(), 1);
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_stream_withdraw_and_stop() {
+        let mut context = get_context(false);
+
+        testing_env!(context.block_timestamp(100_000_000_000).build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        let rate_per_second = 1_000_000_000_000_000_000_000;
+        let stream_id =
+            contract.create_stream(accounts(1).to_string(), U128(rate_per_second), None);
+
+        testing_env!(context
+            .block_timestamp(160_000_000_000)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.withdraw_from_stream(stream_id),
+            U128(60 * rate_per_second)
+        );
+
+        testing_env!(context
+            .block_timestamp(200_000_000_000)
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        let deposit = contract.get_stream(stream_id).deposit.0;
+        assert_eq!(
+            contract.stop_stream(stream_id),
+            U128(deposit - 100 * rate_per_second)
+        );
+
+        testing_env!(context
+            .block_timestamp(300_000_000_000)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        assert_eq!(
+            contract.withdraw_from_stream(stream_id),
+            U128(40 * rate_per_second)
+        );
+        let events = get_logs();
+        assert!(events[0].contains("\"event\":\"stream_withdrawn\""));
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .total_revenue,
+            U128(100 * rate_per_second)
+        );
+        assert_eq!(contract.get_reconciliation().total_streaming, U128(0));
+    }
+
+    #[test]
+    fn test_stream_accrual_saturates_at_deposit() {
+        let mut context = get_context(false);
+
+        testing_env!(context.block_timestamp(100_000_000_000).build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        let stream_id = contract.create_stream(accounts(1).to_string(), U128(u128::MAX), None);
+        let deposit = contract.get_stream(stream_id).deposit;
+
+        testing_env!(context
+            .block_timestamp(200_000_000_000)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(0)
+            .build());
+        assert_eq!(contract.withdraw_from_stream(stream_id), deposit);
+    }
+
+    #[test]
+    fn test_pay_invoice() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let create_epoch = env::epoch_height();
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000_000_000_000_000_000),
+            "1".to_string(),
+            1,
+            create_epoch + 1,
+            "March sponsorship".to_string(),
+            Some(accounts(0).to_string()),
+        );
+        let expiring_invoice_id = contract.create_invoice(
+            U128(1_000_000_000_000_000_000_000_000),
+            "1".to_string(),
+            1,
+            create_epoch,
+            "April sponsorship".to_string(),
+            None,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.pay_invoice(invoice_id.clone());
+        assert_eq!(&contract.get_invoice(invoice_id.clone()).status, "PAID");
+        let transaction = contract
+            .get_transactions_info(accounts(1).to_string(), create_epoch, create_epoch)
+            .pop()
+            .unwrap();
+        assert_eq!(transaction.transaction_id, invoice_id);
+        assert_eq!(transaction.sender_id, accounts(0).to_string());
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(1_000_000_000_000_000_000_000_000)
+        );
+
+        testing_env!(context.epoch_height(create_epoch + 1).build());
+        assert_eq!(&contract.get_invoice(expiring_invoice_id).status, "EXPIRED");
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Receiver bob is blocked")]
+    fn test_claim_by_blocked_receiver() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_blocked_receiver(accounts(1).to_string(), true);
+        assert!(
+            contract
+                .get_account_screening(accounts(1).to_string())
+                .blocked_receiver
+        );
+        contract.claim_and_withdraw(accounts(1).to_string());
+    }
+
+    #[test]
+    fn test_kyc_tier_daily_claim_limit() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+        let amount = contract
+            .get_account_info(accounts(1).to_string())
+            .locked_balance
+            .0;
+        testing_env!(context.attached_deposit(1).build());
+        let max_claim_per_day = 400_000_000_000_000_000_000_000;
+        contract.set_tier_limit(
+            0,
+            TierLimitJson {
+                max_locked_balance: None,
+                max_claim_per_day: Some(U128(max_claim_per_day)),
+                max_lifetime_revenue: None,
+            },
+        );
+
+        // A payment above the daily cap is paid out over several days
+        testing_env!(context
+            .epoch_height(1)
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        assert!(get_logs().contains(&format!(
+            "Claim capped by the daily allowance of KYC tier 0, {} yoctoNEAR stays locked",
+            amount - max_claim_per_day
+        )));
+        settle_claims(&mut contract, &context);
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(amount - max_claim_per_day)
+        );
+        testing_env!(context.block_timestamp(86_400 * 1_000_000_000).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(amount - 2 * max_claim_per_day)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "KYC tier 0 has 0 yoctoNEAR of daily claim allowance left")]
+    fn test_kyc_tier_daily_claim_allowance_used_up() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_tier_limit(
+            0,
+            TierLimitJson {
+                max_locked_balance: None,
+                max_claim_per_day: Some(U128(400_000_000_000_000_000_000_000)),
+                max_lifetime_revenue: None,
+            },
+        );
+
+        testing_env!(context
+            .epoch_height(1)
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        contract.claim_and_withdraw(accounts(1).to_string());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "KYC tier 0 allows at most 1500000000000000000000000 yoctoNEAR locked"
+    )]
+    fn test_kyc_tier_locked_balance_limit() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.register_new_account(accounts(2).to_string(), None);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_tier_limit(
+            0,
+            TierLimitJson {
+                max_locked_balance: Some(U128(1_500_000_000_000_000_000_000_000)),
+                max_claim_per_day: None,
+                max_lifetime_revenue: None,
+            },
+        );
+        contract.set_attester(accounts(3).to_string(), true);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.attest_kyc_tier(accounts(2).to_string(), 1);
+        assert_eq!(
+            contract.get_account_info(accounts(2).to_string()).kyc_tier,
+            1
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        // The attested receiver is not limited, the second payment to bob exceeds tier 0
+        for receiver_id in [accounts(2), accounts(1)] {
+            for transaction_id in ["test1", "test2"] {
+                contract.send_to_contract(
+                    receiver_id.to_string(),
+                    1.0,
+                    0,
+                    "1".to_string(),
+                    transaction_id.to_string(),
+                    None,
+                    None,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_campaign_velocity_limit() {
+        let mut context = get_context(false);
+
+        testing_env!(context.attached_deposit(1).build());
+
+        let mut contract = HurdlePayment::new();
+        contract.set_campaign_velocity_limit(
+            "1".to_string(),
+            Some(VelocityLimitJson {
+                window: 3_600,
+                max_amount: None,
+                max_count: Some(1),
+            }),
+        );
+
+        testing_env!(context
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_campaign("1".to_string(), None, None);
+        // Other senders tagging the campaign neither count towards nor hit its limit
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "other".to_string(),
+            None,
+            None,
+        );
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        for transaction_id in ["test1", "test2"] {
+            contract.send_to_contract(
+                accounts(1).to_string(),
+                1.0,
+                0,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+
+        assert_eq!(
+            contract
+                .get_transactions_info(
+                    accounts(1).to_string(),
+                    env::epoch_height(),
+                    env::epoch_height()
+                )
+                .len(),
+            2
+        );
+        let event = get_logs().pop().unwrap();
+        assert!(event.starts_with("EVENT_JSON:"));
+        assert!(event.contains("\"event\":\"velocity_limit_exceeded\""));
+        assert!(event.contains("\"scope\":\"campaign\""));
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_sender_velocity_limit_applies_to_invoices() {
+        let mut context = get_context(false);
+
+        testing_env!(context.attached_deposit(1).build());
+
+        let mut contract = HurdlePayment::new();
+        contract.set_sender_velocity_limit(
+            accounts(2).to_string(),
+            Some(VelocityLimitJson {
+                window: 3_600,
+                max_amount: None,
+                max_count: Some(0),
+            }),
+        );
+        testing_env!(context
+            .attached_deposit(1_000_000_000_000_000_000_000_000)
+            .build());
+        contract.register_new_account(accounts(1).to_string(), None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000_000_000_000_000_000),
+            "1".to_string(),
+            0,
+            env::epoch_height() + 10,
+            "test".to_string(),
+            None,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.pay_invoice(invoice_id.clone());
+        assert_eq!(contract.get_invoice(invoice_id).status, "OPEN");
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(0)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_get_statement() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        for transaction_id in ["1", "2"] {
+            contract.send_to_contract(
+                accounts(1).to_string(),
+                1.0,
+                1,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+
+        testing_env!(context.epoch_height(1).build());
+        contract.refund_by_transaction_id(
+            accounts(0).to_string(),
+            accounts(1).to_string(),
+            "1".to_string(),
+            0,
+        );
+        testing_env!(context.epoch_height(2).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        testing_env!(context.epoch_height(3).build());
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            1,
+            "1".to_string(),
+            "3".to_string(),
+            None,
+            None,
+        );
+
+        let statement = contract.get_statement(accounts(1).to_string(), 0, 1, 0, 100);
+        assert_eq!(statement.opening_locked_balance, U128(0));
+        assert_eq!(statement.new_locks, U128(2 * amount));
+        assert_eq!(statement.refunds, U128(amount));
+        assert_eq!(statement.claims, U128(0));
+        assert_eq!(statement.closing_locked_balance, U128(amount));
+        assert_eq!(statement.line_items.len(), 3);
+
+        let statement = contract.get_statement(accounts(1).to_string(), 2, 3, 0, 100);
+        assert_eq!(statement.opening_locked_balance, U128(amount));
+        assert_eq!(statement.new_locks, U128(amount));
+        assert_eq!(statement.claims, U128(amount));
+        assert_eq!(statement.closing_locked_balance, U128(amount));
+        let kinds: Vec<&str> = statement
+            .line_items
+            .iter()
+            .map(|line_item| line_item.kind.as_str())
+            .collect();
+        assert_eq!(kinds, vec!["CLAIM", "LOCK"]);
+
+        assert_eq!(statement.next_epoch, None);
+
+        // Each page scans one creation epoch, together they make up the statement of the period
+        let mut statement = contract.get_statement(accounts(1).to_string(), 2, 3, 0, 1);
+        assert_eq!(statement.next_epoch, Some(1));
+        assert_eq!(statement.line_items.len(), 1);
+        while let Some(start_epoch) = statement.next_epoch {
+            statement.merge(contract.get_statement(accounts(1).to_string(), 2, 3, start_epoch, 1));
+        }
+        assert_eq!(statement.opening_locked_balance, U128(amount));
+        assert_eq!(statement.new_locks, U128(amount));
+        assert_eq!(statement.claims, U128(amount));
+        assert_eq!(statement.closing_locked_balance, U128(amount));
+        assert_eq!(statement.line_items.len(), 2);
+    }
+
+    #[test]
+    fn test_campaign_stats() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.register_new_account(accounts(2).to_string(), None);
+        for (receiver_id, transaction_id, amount) in [
+            (accounts(1), "1", 1.0),
+            (accounts(1), "2", 1.0),
+            (accounts(2), "3", 3.0),
+        ] {
+            contract.send_to_contract(
+                receiver_id.to_string(),
+                amount,
+                1,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+        let large_amount = contract.get_transactions_info(accounts(2).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+
+        contract.refund_by_transaction_id(
+            accounts(0).to_string(),
+            accounts(1).to_string(),
+            "1".to_string(),
+            0,
+        );
+        testing_env!(context.epoch_height(2).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+
+        let stats = contract.get_campaign_stats("1".to_string());
+        assert_eq!(stats.transaction_count, 3);
+        assert_eq!(stats.total_locked, U128(2 * amount + large_amount));
+        assert_eq!(stats.total_claimed, U128(amount));
+        assert_eq!(stats.total_refunded, U128(amount));
+        assert_eq!(stats.unique_receivers, 2);
+        assert_eq!(stats.average_hold_time, 2);
+        assert_eq!(stats.refund_rate_bps, 3_333);
+
+        let receivers = contract.get_top_receivers("1".to_string(), 0, 2);
+        assert_eq!(receivers[0].account_id, accounts(2).to_string());
+        assert_eq!(receivers[0].amount, U128(large_amount));
+        assert_eq!(receivers[1].account_id, accounts(1).to_string());
+        assert_eq!(receivers[1].amount, U128(amount));
+        // The largest receiver came last, a page of one still finds it
+        let receivers = contract.get_top_receivers("1".to_string(), 0, 1);
+        assert_eq!(receivers.len(), 1);
+        assert_eq!(receivers[0].account_id, accounts(2).to_string());
+        let receivers = contract.get_top_receivers("1".to_string(), 1, 2);
+        assert_eq!(receivers.len(), 1);
+        assert_eq!(receivers[0].account_id, accounts(1).to_string());
+        assert!(contract.get_top_receivers("2".to_string(), 0, 1).is_empty());
+    }
+
+    #[test]
+    fn test_dashboard() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        for index in 1..4 {
+            contract.register_new_account(accounts(index).to_string(), None);
+        }
+        for (sender_id, receiver_id, transaction_id) in [
+            (accounts(0), accounts(1), "1"),
+            (accounts(0), accounts(1), "2"),
+            (accounts(3), accounts(2), "3"),
+        ] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .predecessor_account_id(sender_id)
+                .build());
+            contract.send_to_contract(
+                receiver_id.to_string(),
+                1.0,
+                1,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+
+        let dashboard = contract.get_dashboard(10);
+        assert_eq!(dashboard.registered_accounts, 3);
+        assert_eq!(dashboard.active_senders, 2);
+        assert_eq!(dashboard.locked_transactions, 3);
+        assert_eq!(dashboard.value_locked, U128(3 * amount));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.refund_by_transaction_id(
+            accounts(3).to_string(),
+            accounts(2).to_string(),
+            "3".to_string(),
+            0,
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .epoch_height(2)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+
+        let dashboard = contract.get_dashboard(1);
+        assert_eq!(dashboard.active_senders, 0);
+        assert_eq!(dashboard.locked_transactions, 0);
+        assert_eq!(dashboard.claimed_transactions, 2);
+        assert_eq!(dashboard.refunded_transactions, 1);
+        assert_eq!(dashboard.value_locked, U128(0));
+        assert_eq!(dashboard.value_claimed_recently, U128(2 * amount));
+        assert_accounting_invariants(&contract);
+
+        testing_env!(context.epoch_height(2 + DASHBOARD_EPOCHS).build());
+        assert_eq!(contract.get_dashboard(1).value_claimed_recently, U128(0));
+        assert_eq!(
+            contract
+                .get_dashboard(DASHBOARD_EPOCHS)
+                .value_claimed_recently,
+            U128(0)
+        );
+
+        let page = contract.get_accounts(1, 5);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].account_id, accounts(2).to_string());
+        assert!(contract.get_accounts(3, 5).is_empty());
+    }
+
+    #[test]
+    fn test_referral_commissions() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(3).to_string(), None);
+        for (account_id, referrer_id) in [(accounts(2), accounts(3)), (accounts(1), accounts(2))] {
+            testing_env!(context.predecessor_account_id(account_id.clone()).build());
+            contract.register_new_account(account_id.to_string(), Some(referrer_id.to_string()));
+        }
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.set_platform_fee(1_000);
+        contract.set_referral_policy(vec![5_000, 2_000], 1);
+        testing_env!(context
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            1,
+            "1".to_string(),
+            "1".to_string(),
+            None,
+            None,
+        );
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+        let fee = amount / 10;
+        // Each commission pays for the storage of its own transaction
+        let storage_cost = env::storage_byte_cost() * Balance::from(TRANSACTION_STORAGE_ESTIMATE);
+
+        testing_env!(context.epoch_height(2).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        let referral = contract.get_transactions_info(accounts(2).to_string(), 2, 2);
+        assert_eq!(referral[0].transaction_type, "REFERRAL");
+        assert_eq!(referral[0].locked_balance, U128(fee / 2 - storage_cost));
+        assert_eq!(referral[0].claimable_at, 4);
+        let referral = contract.get_transactions_info(accounts(3).to_string(), 2, 2);
+        assert_eq!(referral[0].locked_balance, U128(fee / 5 - storage_cost));
+        assert_eq!(
+            contract.get_reconciliation().total_fees,
+            U128(fee - fee / 2 - fee / 5)
+        );
+        let statement = contract.get_statement(accounts(1).to_string(), 2, 2, 0, 100);
+        assert_eq!(statement.claims, U128(amount));
+        assert_eq!(statement.fees, U128(fee));
+        assert_eq!(statement.line_items[1].kind, "FEE");
+        assert_accounting_invariants(&contract);
+
+        // Commissions are paid out of the fee, they are not charged again
+        testing_env!(context.epoch_height(4).build());
+        contract.claim_and_withdraw(accounts(2).to_string());
+        settle_claims(&mut contract, &context);
+        assert_eq!(
+            contract.get_reconciliation().total_fees,
+            U128(fee - fee / 2 - fee / 5)
+        );
+
+        let tree = contract.get_referral_tree(accounts(3).to_string(), 2);
+        assert_eq!(tree.referrals[0].account_id, accounts(2).to_string());
+        assert_eq!(
+            tree.referrals[0].referrals[0].account_id,
+            accounts(1).to_string()
+        );
+        assert!(contract
+            .get_referral_tree(accounts(3).to_string(), 1)
+            .referrals[0]
+            .referrals
+            .is_empty());
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_fees(U128(fee - fee / 2 - fee / 5));
+        assert_eq!(contract.get_reconciliation().total_fees, U128(0));
+    }
+
+    #[test]
+    fn test_platform_fee_is_fixed_at_lock() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_platform_fee(1_000);
+        testing_env!(context
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        for transaction_id in ["test1", "test2"] {
+            contract.send_to_contract(
+                accounts(1).to_string(),
+                1.0,
+                1,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        let transaction = contract
+            .get_transactions_info(accounts(1).to_string(), 0, 0)
+            .remove(0);
+        assert_eq!(transaction.platform_fee_bps, 1_000);
+        let amount = transaction.locked_balance.0;
+        let fee = amount / 10;
+        assert_eq!(contract.get_reconciliation().total_held_fees, U128(2 * fee));
+
+        // Payments locked before the change keep the rate they were sent with
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_platform_fee(500);
+        testing_env!(context.build());
+        contract.refund_by_transaction_id(
+            accounts(0).to_string(),
+            accounts(1).to_string(),
+            "test2".to_string(),
+            0,
+        );
+        assert_eq!(
+            created_transfers(),
+            vec![(accounts(0).to_string(), amount + fee)]
+        );
+
+        testing_env!(context
+            .epoch_height(2)
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        // The receiver gets the whole payment, the sender already paid the fee
+        assert!(created_transfers().contains(&(accounts(1).to_string(), amount)));
+        settle_claims(&mut contract, &context);
+        let reconciliation = contract.get_reconciliation();
+        assert_eq!(reconciliation.total_fees, U128(fee));
+        assert_eq!(reconciliation.total_held_fees, U128(0));
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Platform fee cannot exceed 1000 bps")]
+    fn test_platform_fee_above_maximum() {
+        let mut context = get_context(false);
+
+        testing_env!(context.attached_deposit(1).build());
+
+        let mut contract = HurdlePayment::new();
+        contract.set_platform_fee(1_001);
+    }
+
+    #[test]
+    #[should_panic(expected = "Referrer not found")]
+    fn test_register_with_unknown_referrer() {
+        let mut context = get_context(false);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), Some(accounts(2).to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the account itself can name its referrer")]
+    fn test_register_other_account_with_referrer() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(2).to_string(), None);
+        contract.register_new_account(accounts(1).to_string(), Some(accounts(2).to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the sender can refund the transaction")]
+    fn test_refund_overdue_approval_by_other_account() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_campaign("1".to_string(), None, None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_campaign_approval_policy(
+            "1".to_string(),
+            Some(ApprovalPolicyJson {
+                threshold: U128(0),
+                required_approvals: 1,
+                approvers: vec![accounts(2).to_string()],
+            }),
+        );
+        testing_env!(context
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
+        );
+
+        // The approval is overdue, still only the sender may take the payment back
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .epoch_height(1)
+            .build());
+        contract.refund_by_transaction_id(
+            accounts(0).to_string(),
+            accounts(1).to_string(),
+            "test".to_string(),
+            0,
+        );
+    }
+
+    #[test]
+    fn test_campaign_approval_policy() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_campaign("1".to_string(), None, None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_campaign_approval_policy(
+            "1".to_string(),
+            Some(ApprovalPolicyJson {
+                threshold: U128(2 * 1_000_000_000_000_000_000_000_000),
+                required_approvals: 2,
+                approvers: vec![
+                    accounts(2).to_string(),
+                    accounts(3).to_string(),
+                    accounts(4).to_string(),
+                ],
+            }),
+        );
+        testing_env!(context
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        for (transaction_id, amount) in [("small", 1.0), ("large", 3.0), ("rejected", 3.0)] {
+            contract.send_to_contract(
+                accounts(1).to_string(),
+                amount,
+                0,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        let transactions = contract.get_transactions_info(accounts(1).to_string(), 0, 0);
+        let locked_balance = |transaction_id: &str| {
+            transactions
+                .iter()
+                .find(|transaction| transaction.transaction_id == transaction_id)
+                .unwrap()
+                .locked_balance
+                .0
+        };
+        let (small, large) = (locked_balance("small"), locked_balance("large"));
+
+        testing_env!(context.epoch_height(1).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(2 * large)
+        );
+
+        for approver in [accounts(2), accounts(3)] {
+            testing_env!(context
+                .predecessor_account_id(approver)
+                .attached_deposit(1)
+                .build());
+            contract.approve_transaction(accounts(1).to_string(), 0, "large".to_string());
+            contract.reject_transaction(accounts(1).to_string(), 0, "rejected".to_string());
+        }
+        testing_env!(context
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        let account = contract.get_account_info(accounts(1).to_string());
+        assert_eq!(account.locked_balance, U128(0));
+        assert_eq!(account.total_revenue, U128(small + large));
+        let statuses: Vec<(String, String)> = contract
+            .get_transactions_info(accounts(1).to_string(), 0, 0)
+            .into_iter()
+            .map(|transaction| (transaction.transaction_id, transaction.status))
+            .collect();
+        assert!(statuses.contains(&("large".to_string(), "CLAIM".to_string())));
+        assert!(statuses.contains(&("rejected".to_string(), "REFUND".to_string())));
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_pending_approval_refund() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_campaign("1".to_string(), None, None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_campaign_approval_policy(
+            "1".to_string(),
+            Some(ApprovalPolicyJson {
+                threshold: U128(2 * 1_000_000_000_000_000_000_000_000),
+                required_approvals: 1,
+                approvers: vec![accounts(2).to_string()],
+            }),
+        );
+        // The policy binds the campaign owner's payments, not other senders tagging the campaign
+        for (sender_id, transaction_id) in [(accounts(0), "owner"), (accounts(5), "other")] {
+            testing_env!(context
+                .predecessor_account_id(sender_id)
+                .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+                .build());
+            contract.send_to_contract(
+                accounts(1).to_string(),
+                3.0,
+                0,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+
+        testing_env!(context.epoch_height(1).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(amount)
+        );
+
+        // Nobody approved within the hold time, the sender takes the payment back
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.refund_by_transaction_id(
+            accounts(0).to_string(),
+            accounts(1).to_string(),
+            "owner".to_string(),
+            0,
+        );
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(0)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Approvers must be members of the campaign's organization")]
+    fn test_organization_approval_policy_with_outside_approver() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.create_organization("org".to_string());
+        contract.create_campaign("1".to_string(), None, Some("org".to_string()));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_campaign_approval_policy(
+            "1".to_string(),
+            Some(ApprovalPolicyJson {
+                threshold: U128(0),
+                required_approvals: 1,
+                approvers: vec![accounts(2).to_string()],
+            }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the campaign's approvers can decide on the transaction")]
+    fn test_approve_transaction_by_other_account() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_campaign("1".to_string(), None, None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_campaign_approval_policy(
+            "1".to_string(),
+            Some(ApprovalPolicyJson {
+                threshold: U128(0),
+                required_approvals: 1,
+                approvers: vec![accounts(2).to_string()],
+            }),
+        );
+        testing_env!(context
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "1".to_string(),
+            None,
+            None,
+        );
+        testing_env!(context.attached_deposit(1).build());
+        contract.approve_transaction(accounts(1).to_string(), 0, "1".to_string());
+    }
+
+    #[test]
+    fn test_organization() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_organization("org".to_string());
+        contract.set_organization_member(
+            "org".to_string(),
+            accounts(2).to_string(),
+            Some("SPENDER".to_string()),
+        );
+        contract.set_organization_member(
+            "org".to_string(),
+            accounts(3).to_string(),
+            Some("REFUNDER".to_string()),
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(5 * 1_000_000_000_000_000_000_000_000)
+            .build());
+        contract.deposit_organization_balance("org".to_string());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1_000_000_000_000_000_000_000_000)
+            .build());
+        contract.create_campaign("1".to_string(), Some(1), Some("org".to_string()));
+        testing_env!(context.attached_deposit(1).build());
+        contract.send_from_organization(
+            "org".to_string(),
+            accounts(1).to_string(),
+            U128(1_000_000_000_000_000_000_000_000),
+            1,
+            "1".to_string(),
+            "tx1".to_string(),
+            None,
+            None,
+        );
+        let transaction = &contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0];
+        assert_eq!(transaction.sender_id, accounts(2).to_string());
+        assert_eq!(transaction.organization_id, Some("org".to_string()));
+        let balance = contract
+            .get_organization("org".to_string())
+            .prepaid_balance
+            .0;
+        assert!(balance < 4 * 1_000_000_000_000_000_000_000_000);
+        assert_eq!(
+            contract.get_prepaid_balance(accounts(2).to_string()),
+            U128(0)
+        );
+        assert_accounting_invariants(&contract);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.refund_by_transaction_id(
+            accounts(2).to_string(),
+            accounts(1).to_string(),
+            "tx1".to_string(),
+            0,
+        );
+        assert_eq!(
+            contract.get_organization("org".to_string()).prepaid_balance,
+            U128(balance + 1_000_000_000_000_000_000_000_000)
+        );
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires the ADMIN or SPENDER role in organization org")]
+    fn test_send_from_organization_without_role() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.create_organization("org".to_string());
+        contract.set_organization_member(
+            "org".to_string(),
+            accounts(3).to_string(),
+            Some("VIEWER".to_string()),
+        );
+        contract.deposit_organization_balance("org".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.send_from_organization(
+            "org".to_string(),
+            accounts(1).to_string(),
+            U128(1_000_000_000_000_000_000_000_000),
+            1,
+            "1".to_string(),
+            "tx1".to_string(),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_claim_hook_failure_relocks_payments() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "1".to_string(),
+            None,
+            None,
+        );
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.set_claim_hook_enabled(true);
+        assert!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .claim_hook_enabled
+        );
+
+        testing_env!(context
+            .epoch_height(1)
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        // The claim is reserved but not settled while the hook call is in flight
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance
+                .0,
+            0
+        );
+        assert_eq!(contract.totals.total_claimed, 0);
+        let claims = || {
+            vec![PendingClaim {
+                sender_id: accounts(0).to_string(),
+                organization_id: None,
+                campaign_id: "1".to_string(),
+                created_at: 0,
+                transaction_id: "1".to_string(),
+                amount: U128(amount),
+                fee: U128(0),
+                status: "CLAIM".to_string(),
+            }]
+        };
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        assert!(!contract.on_claim_payout_result(accounts(1).to_string(), 1, claims()));
+        let transaction = &contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0];
+        assert_eq!(transaction.status, "LOCK");
+        assert_eq!(transaction.claimed_amount.0, 0);
+        assert_eq!(
+            contract
+                .get_account_info(accounts(1).to_string())
+                .locked_balance
+                .0,
+            amount
+        );
+        assert_eq!(contract.totals.total_claimed, 0);
+        assert_accounting_invariants(&contract);
 
-    fn get_context(is_view: bool) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
-            .current_account_id(accounts(0))
-            .signer_account_id(accounts(0))
-            .predecessor_account_id(accounts(0))
-            .is_view(is_view);
-        builder
+        // The next claim pays the locked payment out again and settles once the hook succeeds
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])]
+        );
+        assert!(contract.on_claim_payout_result(accounts(1).to_string(), 1, claims()));
+        let transaction = &contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0];
+        assert_eq!(transaction.status, "CLAIM");
+        assert_eq!(transaction.claimed_amount.0, amount);
+        assert_eq!(contract.totals.total_claimed, amount);
+        assert_accounting_invariants(&contract);
     }
-    #[test]
-    fn test_init_contract() {
-        let context = get_context(false);
 
-        testing_env!(context.build());
-
-        let contract = HurdlePayment::new();
-        assert_eq!(contract.owner_id, env::current_account_id());
+    fn milestone(share_bps: u16, approver_id: ValidAccountId, deadline: u64) -> Milestone {
+        Milestone {
+            share_bps,
+            approver_id: approver_id.to_string(),
+            oracle: false,
+            deadline,
+            released_at: None,
+            reclaimed_at: None,
+        }
     }
 
     #[test]
-    fn test_register_new_account() {
-        let context = get_context(false);
+    fn test_milestone_release_and_reclaim() {
+        let mut context = get_context(false);
 
         testing_env!(context.build());
 
         let mut contract = HurdlePayment::new();
-        contract.register_new_account(accounts(0).to_string());
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "1".to_string(),
+            None,
+            Some(PayoutSchedule::Milestones {
+                milestones: vec![
+                    milestone(6_000, accounts(2), 1),
+                    milestone(4_000, accounts(3), 2),
+                ],
+            }),
+        );
+        let transaction = &contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0];
+        assert_eq!(transaction.transaction_type, "CONDITIONAL");
+        let amount = transaction.locked_balance.0;
+        let remaining = amount - amount * 6 / 10;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.release_milestone(accounts(1).to_string(), 0, "1".to_string(), 0);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .epoch_height(1)
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
         assert_eq!(
             contract
-                .accounts
-                .get(&accounts(0).to_string())
-                .unwrap()
-                .total_revenue,
-            0
+                .get_account_info(accounts(1).to_string())
+                .locked_balance,
+            U128(remaining)
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .epoch_height(4)
+            .build());
+        assert_eq!(
+            contract.reclaim_milestones(accounts(1).to_string(), 0, "1".to_string()),
+            U128(remaining)
         );
+        let transaction = &contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0];
+        assert_eq!(transaction.status, "CLAIM");
+        let statement = contract.get_statement(accounts(1).to_string(), 0, 4, 0, 100);
+        assert_eq!(statement.claims, U128(amount * 6 / 10));
+        assert_eq!(statement.refunds, U128(remaining));
+        assert_eq!(statement.closing_locked_balance, U128(0));
+        let stats = contract.get_campaign_stats("1".to_string());
+        assert_eq!(stats.total_refunded, U128(remaining));
+        assert_eq!(stats.refund_rate_bps, 0);
+        assert_eq!(contract.get_dashboard(1).locked_transactions, 0);
+        assert_accounting_invariants(&contract);
     }
 
     #[test]
-    fn test_send_to_contract() {
-        let context = get_context(false);
+    #[should_panic(expected = "Only the milestone's approver can release it")]
+    fn test_release_milestone_by_other_account() {
+        let mut context = get_context(false);
 
         testing_env!(context.build());
 
         let mut contract = HurdlePayment::new();
-        contract.register_new_account(accounts(1).to_string());
+        contract.register_new_account(accounts(1).to_string(), None);
         contract.send_to_contract(
             accounts(1).to_string(),
             1.0,
             0,
-            "12".to_string(),
-            "test1".to_string(),
+            "1".to_string(),
+            "1".to_string(),
+            None,
+            Some(PayoutSchedule::Milestones {
+                milestones: vec![milestone(10_000, accounts(2), 1)],
+            }),
         );
-        let account_info = contract.get_account_info(accounts(1).to_string());
-        assert_eq!(account_info.total_revenue, U128(999999999999999983222784));
-        assert_eq!(account_info.locked_balance, U128(999999999999999983222784));
-        let today = env::epoch_height();
-        assert_eq!(account_info.last_unlock_at, today);
+        testing_env!(context.attached_deposit(1).build());
+        contract.release_milestone(accounts(1).to_string(), 0, "1".to_string(), 0);
+    }
+
+    #[test]
+    fn test_blocked_sender_reclaims_milestones() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
         contract.send_to_contract(
             accounts(1).to_string(),
             1.0,
             0,
             "1".to_string(),
-            "test2".to_string(),
-        );
-        let account_info = contract.get_account_info(accounts(1).to_string());
-        let mut transactions = contract.get_transactions_info(
-            accounts(1).to_string(),
-            env::epoch_height(),
-            env::epoch_height(),
+            "1".to_string(),
+            None,
+            Some(PayoutSchedule::Milestones {
+                milestones: vec![milestone(10_000, accounts(2), 1)],
+            }),
         );
-        assert_eq!(transactions.len(), 2);
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_blocked_sender(accounts(0).to_string(), true);
+
+        testing_env!(context.epoch_height(2).build());
         assert_eq!(
-            account_info.total_revenue,
-            U128(999999999999999983222784 * 2)
+            contract.reclaim_milestones(accounts(1).to_string(), 0, "1".to_string()),
+            U128(amount)
         );
+        assert_accounting_invariants(&contract);
+    }
 
-        assert_eq!(
-            account_info.locked_balance,
-            U128(999999999999999983222784 * 2)
+    #[test]
+    #[should_panic(expected = "Schedule offset is too large")]
+    fn test_milestone_deadline_overflow() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "1".to_string(),
+            None,
+            Some(PayoutSchedule::Milestones {
+                milestones: vec![milestone(10_000, accounts(2), u64::MAX)],
+            }),
         );
     }
 
     #[test]
-    fn test_unlock_balance() {
+    fn test_milestone_oracle() {
         let context = get_context(false);
 
         testing_env!(context.build());
 
         let mut contract = HurdlePayment::new();
-        contract.register_new_account(accounts(1).to_string());
+        contract.register_new_account(accounts(1).to_string(), None);
         contract.send_to_contract(
             accounts(1).to_string(),
             1.0,
             0,
             "1".to_string(),
             "1".to_string(),
+            None,
+            Some(PayoutSchedule::Milestones {
+                milestones: vec![Milestone {
+                    oracle: true,
+                    ..milestone(10_000, accounts(4), 1)
+                }],
+            }),
         );
+        contract.check_milestone_oracle(accounts(1).to_string(), 0, "1".to_string(), 0);
 
-        let locked_amount = contract
-            .accounts
-            .get(&accounts(1).to_string())
-            .unwrap()
-            .locked_balance;
-        contract.claim_and_withdraw(accounts(1).to_string());
-        let mut transactions = contract.get_transactions_info(
+        let answer = |met: &str| vec![PromiseResult::Successful(met.as_bytes().to_vec())];
+        testing_env!(
+            context.build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            answer("false")
+        );
+        assert!(!contract.on_milestone_oracle_result(
             accounts(1).to_string(),
-            env::epoch_height(),
-            env::epoch_height(),
+            0,
+            "1".to_string(),
+            0
+        ));
+        testing_env!(
+            context.build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            answer("true")
         );
+        assert!(contract.on_milestone_oracle_result(
+            accounts(1).to_string(),
+            0,
+            "1".to_string(),
+            0
+        ));
+        let transaction =
+            contract.internal_get_transaction(&accounts(1).to_string(), 0, &"1".to_string());
+        assert_eq!(transaction.vested_amount(0), transaction.locked_balance);
+    }
 
-        assert_eq!(transactions.pop().unwrap().status, "CLAIM");
+    #[test]
+    fn test_close_account() {
+        let mut context = get_context(false);
 
-        assert_eq!(
-            contract
-                .get_account_info(accounts(1).to_string())
-                .locked_balance,
-            U128(0)
-        );
+        testing_env!(context.build());
 
-        assert_eq!(
-            contract
-                .get_account_info(accounts(1).to_string())
-                .total_revenue,
-            U128(locked_amount)
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.send_to_contract(
+            accounts(1).to_string(),
+            1.0,
+            0,
+            "1".to_string(),
+            "test".to_string(),
+            None,
+            None,
         );
 
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        let before_storage_usage = env::storage_usage();
+        contract.close_account();
+        assert!(contract.accounts.get(&accounts(1).to_string()).is_none());
+        // The released storage is paid out once, the sender gets back what its transaction used
+        let transfers = created_transfers();
+        let released =
+            env::storage_byte_cost() * Balance::from(before_storage_usage - env::storage_usage());
+        settle_claims(&mut contract, &context);
+        let claimed = contract.get_reconciliation().total_claimed.0;
         assert_eq!(
-            contract
-                .get_account_info(accounts(1).to_string())
-                .total_revenue,
-            U128(locked_amount)
+            transfers.iter().map(|(_, amount)| amount).sum::<Balance>(),
+            claimed + released
         );
+        assert!(transfers
+            .iter()
+            .any(|(receiver_id, _)| receiver_id == &accounts(2).to_string()));
+        assert_accounting_invariants(&contract);
     }
 
     #[test]
-    fn test_refund_by_transaction_id() {
-        let context = get_context(false);
+    fn test_close_account_with_failed_payout() {
+        let mut context = get_context(false);
 
         testing_env!(context.build());
 
         let mut contract = HurdlePayment::new();
-        contract.register_new_account(accounts(1).to_string());
-        contract.register_new_account(accounts(2).to_string());
+        contract.register_new_account(accounts(1).to_string(), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
         contract.send_to_contract(
             accounts(1).to_string(),
             1.0,
-            1,
+            0,
             "1".to_string(),
             "test".to_string(),
+            None,
+            None,
         );
+        let amount = contract.get_transactions_info(accounts(1).to_string(), 0, 0)[0]
+            .locked_balance
+            .0;
 
-        contract.refund_by_transaction_id(
-            accounts(0).to_string(),
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.close_account();
+        // The final payout bounces after the account is gone, so the payment goes back to its sender
+        testing_env!(
+            context
+                .storage_usage(env::storage_usage())
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        assert!(!contract.on_claim_payout_result(
             accounts(1).to_string(),
+            0,
+            vec![PendingClaim {
+                sender_id: accounts(2).to_string(),
+                organization_id: None,
+                campaign_id: "1".to_string(),
+                created_at: 0,
+                transaction_id: "test".to_string(),
+                amount: U128(amount),
+                fee: U128(0),
+                status: "CLAIM".to_string(),
+            }]
+        ));
+        assert_eq!(created_transfers(), vec![(accounts(2).to_string(), amount)]);
+        let reconciliation = contract.get_reconciliation();
+        assert_eq!(reconciliation.total_claimed.0, 0);
+        assert_eq!(reconciliation.total_refunded.0, amount);
+        assert_eq!(contract.get_dashboard(1).locked_transactions, 0);
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account still has open invoices")]
+    fn test_close_account_with_open_invoice() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.create_invoice(
+            U128(1_000_000_000_000_000_000_000_000),
+            "1".to_string(),
+            0,
+            env::epoch_height() + 10,
             "test".to_string(),
-            env::epoch_height(),
+            None,
         );
-        let transaction = contract
-            .get_transactions_info(
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.close_account();
+    }
+
+    #[test]
+    fn test_prune_settled_epochs() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        let mut contract = HurdlePayment::new();
+        contract.register_new_account(accounts(1).to_string(), None);
+        let create_epoch = env::epoch_height();
+        for transaction_id in ["test1", "test2"] {
+            contract.send_to_contract(
                 accounts(1).to_string(),
-                env::epoch_height(),
-                env::epoch_height(),
-            )
-            .pop()
-            .unwrap();
-        assert_eq!(transaction.claimable_at, env::epoch_height() + 2);
-        assert_eq!(&transaction.status, "REFUND");
-        assert_eq!(
-            contract
-                .get_account_info(accounts(1).to_string())
-                .locked_balance,
-            U128(0)
-        );
+                1.0,
+                0,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
 
+        testing_env!(context.epoch_height(create_epoch + 60).build());
+        contract.prune_settled_epochs(accounts(1).to_string());
         assert_eq!(
             contract
-                .get_account_info(accounts(1).to_string())
-                .total_revenue,
-            U128(0)
+                .get_transactions_info(accounts(1).to_string(), create_epoch, create_epoch)
+                .len(),
+            2
         );
+
+        testing_env!(context.epoch_height(create_epoch + 61).build());
+        contract.prune_settled_epochs(accounts(1).to_string());
+        assert!(contract
+            .get_transactions_info(accounts(1).to_string(), create_epoch, create_epoch)
+            .is_empty());
+        assert_accounting_invariants(&contract);
     }
 
     #[test]
-    fn test_refund_by_epoch() {
-        let context = get_context(false);
+    fn test_prune_settled_epochs_refunds_storage() {
+        let mut context = get_context(false);
 
         testing_env!(context.build());
 
         let mut contract = HurdlePayment::new();
-        contract.register_new_account(accounts(1).to_string());
-        contract.register_new_account(accounts(2).to_string());
+        contract.register_new_account(accounts(1).to_string(), None);
+        let create_epoch = env::epoch_height();
+        for (sender_id, transaction_id) in [(accounts(2), "test1"), (accounts(3), "test2")] {
+            testing_env!(context
+                .predecessor_account_id(sender_id.clone())
+                .storage_usage(env::storage_usage())
+                .build());
+            contract.send_to_contract(
+                accounts(1).to_string(),
+                1.0,
+                0,
+                "1".to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .storage_usage(env::storage_usage())
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
+
+        testing_env!(context
+            .epoch_height(create_epoch + 61)
+            .storage_usage(env::storage_usage())
+            .build());
+        let before_storage_usage = env::storage_usage();
+        contract.prune_settled_epochs(accounts(1).to_string());
+        let released =
+            env::storage_byte_cost() * Balance::from(before_storage_usage - env::storage_usage());
+        let transfers = created_transfers();
+        // Each sender gets their transaction back, the first one also the bucket it opened
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].0, accounts(2).to_string());
+        assert_eq!(transfers[1].0, accounts(3).to_string());
+        assert!(transfers[0].1 > transfers[1].1);
+        assert_eq!(transfers[0].1 + transfers[1].1, released);
+        assert_accounting_invariants(&contract);
+    }
+
+    #[test]
+    fn test_send_to_contract_auto_register() {
+        let mut context = get_context(false);
+
+        testing_env!(context.attached_deposit(1).build());
+
+        let mut contract = HurdlePayment::new();
+        contract.set_auto_register_enabled(true);
+
+        testing_env!(context
+            .attached_deposit(10 * 1_000_000_000_000_000_000_000_000)
+            .build());
         contract.send_to_contract(
-            accounts(1).to_string(),
+            accounts(2).to_string(),
             1.0,
-            1,
+            0,
             "1".to_string(),
             "test".to_string(),
+            None,
+            None,
         );
-        contract.send_to_contract(
-            accounts(1).to_string(),
-            1.0,
-            1,
-            "1".to_string(),
-            "test1".to_string(),
+        assert_eq!(
+            contract
+                .get_account_info(accounts(2).to_string())
+                .locked_balance,
+            U128(999999999999999983222784)
         );
+        assert_accounting_invariants(&contract);
+    }
 
-        contract.refund_by_epoch(accounts(0).to_string(), accounts(1).to_string(), 0);
-        let transaction = contract
-            .get_transactions_info(
-                accounts(1).to_string(),
-                env::epoch_height(),
-                env::epoch_height(),
-            )
-            .pop()
+    #[test]
+    #[should_panic(expected = "Only version 0 state can be migrated")]
+    fn test_migrate_current_state() {
+        let context = get_context(false);
+
+        testing_env!(context.build());
+
+        env::state_write(&HurdlePayment::new());
+        HurdlePayment::migrate();
+    }
+
+    #[test]
+    fn test_migrate_version_0_state() {
+        let mut context = get_context(false);
+
+        testing_env!(context.build());
+
+        // Version 0 keyed every receiver's map of an epoch by the epoch alone
+        let mut old_accounts = LookupMap::new(StorageKey::AccountKey);
+        let mut shared_transactions = UnorderedMap::new(StorageKey::AccountTransaction {
+            account_hash: env::sha256(0.to_string().as_bytes()),
+        });
+        for (receiver, transaction_id, status) in [
+            (1, "locked", "LOCK"),
+            (1, "claimed", "CLAIM"),
+            (2, "other", "LOCK"),
+        ] {
+            shared_transactions.insert(
+                &transaction_id.to_string(),
+                &OldTransferTransaction {
+                    sender_id: accounts(0).to_string(),
+                    receiver_id: accounts(receiver).to_string(),
+                    campaign_id: "1".to_string(),
+                    locked_balance: 100,
+                    created_at: 0,
+                    claimable_at: 2,
+                    status: status.to_string(),
+                },
+            );
+        }
+        for (receiver, locked_balance) in [(1, 100), (2, 100)] {
+            let account_id = accounts(receiver).to_string();
+            let mut transactions = UnorderedMap::new(StorageKey::AccountTransactionByDate {
+                account_hash: env::sha256(account_id.as_bytes()),
+            });
+            transactions.insert(&0, &shared_transactions);
+            old_accounts.insert(
+                &account_id,
+                &OldAccount {
+                    locked_balance,
+                    total_revenue: 200,
+                    transactions,
+                    last_unlock_at: 0,
+                },
+            );
+        }
+        env::state_write(&OldHurdlePayment {
+            owner_id: accounts(3).to_string(),
+            accounts: old_accounts,
+        });
+
+        let mut contract = HurdlePayment::migrate();
+        assert_eq!(contract.owner_id, accounts(3).to_string());
+        assert_eq!(contract.state_version, STATE_VERSION);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let account_ids = vec![
+            accounts(1).to_string(),
+            accounts(2).to_string(),
+            accounts(4).to_string(),
+        ];
+        assert_eq!(contract.migrate_accounts(account_ids.clone()), 2);
+        assert_eq!(contract.migrate_accounts(account_ids), 0);
+
+        let transactions = contract.get_transactions_info(accounts(1).to_string(), 0, 0);
+        assert_eq!(transactions.len(), 2);
+        let locked = transactions
+            .iter()
+            .find(|transaction| transaction.transaction_id == "locked")
             .unwrap();
-        assert_eq!(transaction.claimable_at, env::epoch_height() + 2);
-        assert_eq!(&transaction.status, "REFUND");
+        assert_eq!(locked.refundable_until, 2);
+        assert_eq!(locked.claimed_amount, U128(0));
         assert_eq!(
             contract
-                .get_account_info(accounts(1).to_string())
-                .locked_balance,
-            U128(0)
+                .get_transactions_info(accounts(2).to_string(), 0, 0)
+                .len(),
+            1
+        );
+        let reconciliation = contract.get_reconciliation();
+        assert_eq!(reconciliation.total_locked, U128(200));
+        assert_eq!(reconciliation.total_claimed, U128(100));
+        let registered: Vec<AccountId> = contract
+            .get_accounts(0, 10)
+            .into_iter()
+            .map(|account| account.account_id)
+            .collect();
+        assert_eq!(
+            registered,
+            vec![accounts(1).to_string(), accounts(2).to_string()]
         );
+        assert_accounting_invariants(&contract);
 
+        testing_env!(context
+            .epoch_height(2)
+            .attached_deposit(10_000_000_000_000_000_000_000)
+            .build());
+        contract.claim_and_withdraw(accounts(1).to_string());
+        settle_claims(&mut contract, &context);
         assert_eq!(
             contract
                 .get_account_info(accounts(1).to_string())
-                .total_revenue,
+                .locked_balance,
             U128(0)
         );
+        assert_accounting_invariants(&contract);
     }
 }
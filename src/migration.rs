@@ -0,0 +1,83 @@
+use crate::*;
+
+// Bumped whenever `migrate` learns to read another layout
+pub const STATE_VERSION: u32 = 1;
+
+// Contract state as deployed before versioning, version 0
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct OldHurdlePayment {
+  pub owner_id: AccountId,
+  pub accounts: LookupMap<AccountId, OldAccount>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct OldAccount {
+  pub locked_balance: Balance,
+  pub total_revenue: Balance,
+  pub transactions: UnorderedMap<u64, UnorderedMap<String, OldTransferTransaction>>,
+  pub last_unlock_at: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct OldTransferTransaction {
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub campaign_id: String,
+  pub locked_balance: Balance,
+  pub created_at: u64,
+  pub claimable_at: u64,
+  pub status: String, // LOCK CLAIM REFUND
+}
+
+impl OldTransferTransaction {
+  // Version 0 refunded while the payment was not claimable yet, the hold time was the window
+  pub fn migrate(self) -> TransferTransaction {
+    let claimed_amount = if self.status == "CLAIM" {
+      self.locked_balance
+    } else {
+      0
+    };
+    TransferTransaction {
+      sender_id: self.sender_id,
+      receiver_id: self.receiver_id,
+      campaign_id: self.campaign_id,
+      locked_balance: self.locked_balance,
+      created_at: self.created_at,
+      claimable_at: self.claimable_at,
+      refundable_until: self.claimable_at,
+      schedule: PayoutSchedule::Lump,
+      claimed_amount,
+      claims: Vec::new(),
+      refunded_at: None,
+      status: self.status,
+      transaction_type: "PAYMENT".to_string(),
+      approval: None,
+      organization_id: None,
+      platform_fee_bps: 0,
+      held_fee: 0,
+    }
+  }
+}
+
+// Version 0 predates `state_version`, later layouts carry it. None for a layout neither reads
+pub fn stored_state_version(state: &[u8]) -> Option<u32> {
+  if let Ok(contract) = HurdlePayment::try_from_slice(state) {
+    return Some(contract.state_version);
+  }
+  OldHurdlePayment::try_from_slice(state).ok().map(|_| 0)
+}
+
+// Removes the account's record while it is in the old layout, `accounts` can't read it back
+// to replace it. None once the account is migrated
+pub(crate) fn take_old_account(account_id: &AccountId) -> Option<OldAccount> {
+  let key = [
+    StorageKey::AccountKey.try_to_vec().unwrap(),
+    account_id.try_to_vec().unwrap(),
+  ]
+  .concat();
+  let old_account = env::storage_read(&key).and_then(|raw| OldAccount::try_from_slice(&raw).ok());
+  if old_account.is_some() {
+    env::storage_remove(&key);
+  }
+  old_account
+}
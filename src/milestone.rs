@@ -0,0 +1,15 @@
+use near_sdk::{ext_contract, Gas};
+
+pub const MILESTONE_ORACLE_GAS: Gas = 10_000_000_000_000;
+pub const MILESTONE_CALLBACK_GAS: Gas = 10_000_000_000_000;
+
+// Interface an oracle contract implements to release milestones it approves
+#[ext_contract(ext_milestone_oracle)]
+pub trait MilestoneOracle {
+  fn is_milestone_met(
+    &self,
+    receiver_id: AccountId,
+    transaction_id: String,
+    milestone_index: u64,
+  ) -> bool;
+}
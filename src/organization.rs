@@ -0,0 +1,55 @@
+use crate::*;
+
+// VIEWER grants nothing on-chain where every view is public, it is kept for off-chain dashboards
+pub const ORGANIZATION_ROLES: [&str; 4] = ["ADMIN", "SPENDER", "REFUNDER", "VIEWER"];
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Organization {
+  pub members: Vec<(AccountId, String)>, // (account_id, role)
+  pub prepaid_balance: Balance,
+}
+
+impl Organization {
+  pub fn role(&self, account_id: &AccountId) -> Option<&str> {
+    self
+      .members
+      .iter()
+      .find(|(member_id, _)| member_id == account_id)
+      .map(|(_, role)| role.as_str())
+  }
+
+  pub fn has_role(&self, account_id: &AccountId, roles: &[&str]) -> bool {
+    self
+      .role(account_id)
+      .is_some_and(|role| roles.contains(&role))
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrganizationMemberJson {
+  pub account_id: AccountId,
+  pub role: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrganizationJson {
+  pub organization_id: String,
+  pub members: Vec<OrganizationMemberJson>,
+  pub prepaid_balance: U128,
+}
+
+impl OrganizationJson {
+  pub fn from(organization_id: String, organization: Organization) -> Self {
+    OrganizationJson {
+      organization_id,
+      members: organization
+        .members
+        .into_iter()
+        .map(|(account_id, role)| OrganizationMemberJson { account_id, role })
+        .collect(),
+      prepaid_balance: U128(organization.prepaid_balance),
+    }
+  }
+}
@@ -0,0 +1,38 @@
+use crate::*;
+
+// Deepest referrer that can earn a commission on a claim
+pub const MAX_REFERRAL_LEVELS: usize = 5;
+pub const REFERRAL_CAMPAIGN_ID: &str = "referral";
+pub const MAX_PLATFORM_FEE_BPS: u16 = 1_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct FeePolicy {
+  pub platform_fee_bps: u16, // charged to the sender on top of every payment
+  pub referral_level_bps: Vec<u16>, // share of the platform fee, direct referrer first
+  pub referral_cash_hold_time: u64, // epochs
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeePolicyJson {
+  pub platform_fee_bps: u16,
+  pub referral_level_bps: Vec<u16>,
+  pub referral_cash_hold_time: u64, // days
+}
+
+impl FeePolicyJson {
+  pub fn from(policy: &FeePolicy) -> Self {
+    FeePolicyJson {
+      platform_fee_bps: policy.platform_fee_bps,
+      referral_level_bps: policy.referral_level_bps.clone(),
+      referral_cash_hold_time: policy.referral_cash_hold_time / 2,
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralNodeJson {
+  pub account_id: AccountId,
+  pub referrals: Vec<ReferralNodeJson>,
+}
@@ -0,0 +1,155 @@
+use crate::*;
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StatementLineJson {
+  pub epoch: u64,
+  pub kind: String, // LOCK CLAIM FEE REFUND
+  pub transaction_id: String,
+  pub sender_id: AccountId,
+  pub campaign_id: String,
+  pub amount: U128,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StatementJson {
+  pub account_id: AccountId,
+  pub from_epoch: u64,
+  pub to_epoch: u64,
+  pub opening_locked_balance: U128,
+  pub new_locks: U128,
+  pub claims: U128,
+  pub refunds: U128,
+  pub fees: U128, // paid by the senders on top of the account's payouts
+  pub closing_locked_balance: U128,
+  pub line_items: Vec<StatementLineJson>,
+  pub next_epoch: Option<u64>, // creation epoch the next page starts at, `None` on the last page
+}
+
+// Locked balance of a transaction at the end of `epoch`
+fn locked_after(transaction: &TransferTransaction, epoch: u64) -> Balance {
+  if transaction.created_at > epoch || transaction.refunded_at.is_some_and(|at| at <= epoch) {
+    return 0;
+  }
+  let claimed: Balance = transaction
+    .claims
+    .iter()
+    .filter(|(at, _, _)| *at <= epoch)
+    .map(|(_, amount, _)| amount)
+    .sum();
+  let reclaimed: Balance = transaction
+    .reclaims()
+    .iter()
+    .filter(|(at, _)| *at <= epoch)
+    .map(|(_, amount)| amount)
+    .sum();
+  transaction.locked_balance - claimed - reclaimed
+}
+
+impl StatementJson {
+  // Covers the epochs from_epoch..=to_epoch for the transactions created in up to `limit` epochs
+  // from start_epoch. Settled transactions pruned after the retention period no longer show up
+  pub fn from(
+    account_id: AccountId,
+    account: &Account,
+    from_epoch: u64,
+    to_epoch: u64,
+    start_epoch: u64,
+    limit: u64,
+  ) -> Self {
+    let end_epoch = std::cmp::min(
+      to_epoch.saturating_add(1),
+      start_epoch.saturating_add(limit),
+    );
+    let mut statement = StatementJson {
+      account_id,
+      from_epoch,
+      to_epoch,
+      opening_locked_balance: U128(0),
+      new_locks: U128(0),
+      claims: U128(0),
+      refunds: U128(0),
+      fees: U128(0),
+      closing_locked_balance: U128(0),
+      line_items: Vec::new(),
+      next_epoch: Some(end_epoch).filter(|epoch| *epoch <= to_epoch),
+    };
+    let in_period = |epoch: u64| from_epoch <= epoch && epoch <= to_epoch;
+    for create_epoch in start_epoch..end_epoch {
+      let transactions = match account.transactions.get(&create_epoch) {
+        Some(transactions) => transactions,
+        None => continue,
+      };
+      for (transaction_id, transaction) in transactions.iter() {
+        if from_epoch > 0 {
+          statement.opening_locked_balance.0 += locked_after(&transaction, from_epoch - 1);
+        }
+        statement.closing_locked_balance.0 += locked_after(&transaction, to_epoch);
+
+        let line_item = |epoch: u64, kind: &str, amount: Balance| StatementLineJson {
+          epoch,
+          kind: kind.to_string(),
+          transaction_id: transaction_id.clone(),
+          sender_id: transaction.sender_id.clone(),
+          campaign_id: transaction.campaign_id.clone(),
+          amount: U128(amount),
+        };
+        let mut line_items = Vec::new();
+        if in_period(transaction.created_at) {
+          statement.new_locks.0 += transaction.locked_balance;
+          line_items.push(line_item(
+            transaction.created_at,
+            "LOCK",
+            transaction.locked_balance,
+          ));
+        }
+        for (epoch, amount, fee) in transaction.claims.iter() {
+          if in_period(*epoch) {
+            statement.claims.0 += amount;
+            line_items.push(line_item(*epoch, "CLAIM", *amount));
+            if *fee > 0 {
+              statement.fees.0 += fee;
+              line_items.push(line_item(*epoch, "FEE", *fee));
+            }
+          }
+        }
+        let reclaims = transaction.reclaims();
+        for (epoch, amount) in reclaims.iter() {
+          if in_period(*epoch) {
+            statement.refunds.0 += amount;
+            line_items.push(line_item(*epoch, "REFUND", *amount));
+          }
+        }
+        // A transaction settled by milestone reclaims is already covered above
+        let refunded_at = transaction.refunded_at.filter(|_| reclaims.is_empty());
+        if let Some(epoch) = refunded_at.filter(|epoch| in_period(*epoch)) {
+          statement.refunds.0 += transaction.locked_balance;
+          line_items.push(line_item(epoch, "REFUND", transaction.locked_balance));
+        }
+        statement.line_items.append(&mut line_items);
+      }
+    }
+    statement.sort_line_items();
+    statement
+  }
+
+  // Every total sums over transactions, so the pages of a period add up to its statement
+  pub fn merge(&mut self, mut page: StatementJson) {
+    self.opening_locked_balance.0 += page.opening_locked_balance.0;
+    self.new_locks.0 += page.new_locks.0;
+    self.claims.0 += page.claims.0;
+    self.refunds.0 += page.refunds.0;
+    self.fees.0 += page.fees.0;
+    self.closing_locked_balance.0 += page.closing_locked_balance.0;
+    self.line_items.append(&mut page.line_items);
+    self.next_epoch = page.next_epoch;
+    self.sort_line_items();
+  }
+
+  fn sort_line_items(&mut self) {
+    self
+      .line_items
+      .sort_by(|a, b| (a.epoch, &a.transaction_id).cmp(&(b.epoch, &b.transaction_id)));
+  }
+}
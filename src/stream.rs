@@ -0,0 +1,59 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Stream {
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub rate_per_second: Balance,
+  pub deposit: Balance,
+  pub withdrawn: Balance,
+  pub started_at: u64, // seconds
+  pub stopped_at: Option<u64>,
+  pub status: String, // ACTIVE STOPPED
+}
+
+impl Stream {
+  pub fn accrued_amount(&self, now: u64) -> Balance {
+    let end = self.stopped_at.unwrap_or(now);
+    if end <= self.started_at {
+      return 0;
+    }
+    // Saturates so a long-running stream still settles at its deposit
+    let streamed = self
+      .rate_per_second
+      .saturating_mul(Balance::from(end - self.started_at));
+    std::cmp::min(streamed, self.deposit)
+  }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StreamJson {
+  pub stream_id: u64,
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub rate_per_second: U128,
+  pub deposit: U128,
+  pub withdrawn: U128,
+  pub accrued: U128,
+  pub started_at: u64,
+  pub stopped_at: Option<u64>,
+  pub status: String,
+}
+
+impl StreamJson {
+  pub fn from(stream_id: u64, stream: Stream) -> Self {
+    StreamJson {
+      stream_id,
+      accrued: U128(stream.accrued_amount(now_in_seconds())),
+      sender_id: stream.sender_id,
+      receiver_id: stream.receiver_id,
+      rate_per_second: U128(stream.rate_per_second),
+      deposit: U128(stream.deposit),
+      withdrawn: U128(stream.withdrawn),
+      started_at: stream.started_at,
+      stopped_at: stream.stopped_at,
+      status: stream.status,
+    }
+  }
+}
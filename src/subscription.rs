@@ -0,0 +1,52 @@
+use crate::*;
+
+// Missed periods a single charge catches up on, the rest waits for the next call
+pub const MAX_CATCH_UP_PERIODS: u64 = 10;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Subscription {
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub campaign_id: String,
+  pub amount: Balance,
+  pub period: u64,
+  pub cash_hold_time: u64,
+  pub max_periods: u64, // 0 charges until the subscription is cancelled
+  pub charged_periods: u64,
+  pub next_charge_at: u64,
+  pub status: String, // ACTIVE PAUSED CANCELLED COMPLETED
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SubscriptionJson {
+  pub subscription_id: u64,
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub campaign_id: String,
+  pub amount: U128,
+  pub period: u64,
+  pub cash_hold_time: u64,
+  pub max_periods: u64,
+  pub charged_periods: u64,
+  pub next_charge_at: u64,
+  pub status: String,
+}
+
+impl SubscriptionJson {
+  pub fn from(subscription_id: u64, subscription: Subscription) -> Self {
+    SubscriptionJson {
+      subscription_id,
+      sender_id: subscription.sender_id,
+      receiver_id: subscription.receiver_id,
+      campaign_id: subscription.campaign_id,
+      amount: U128(subscription.amount),
+      period: subscription.period,
+      cash_hold_time: subscription.cash_hold_time,
+      max_periods: subscription.max_periods,
+      charged_periods: subscription.charged_periods,
+      next_charge_at: subscription.next_charge_at,
+      status: subscription.status,
+    }
+  }
+}
@@ -1,5 +1,119 @@
 use crate::*;
 
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Tranche {
+  pub release_after: u64,
+  pub share_bps: u16, // share of the transaction amount in basis points
+}
+
+// Released by its approver account, or by the approver's `is_milestone_met` when it is an oracle
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Milestone {
+  pub share_bps: u16,
+  pub approver_id: AccountId,
+  pub oracle: bool,
+  pub deadline: u64, // the sender can reclaim the milestone once it passes unreleased
+  pub released_at: Option<u64>,
+  pub reclaimed_at: Option<u64>,
+}
+
+// Snapshot of the campaign's approval policy taken when a large payment is created
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransactionApproval {
+  pub required_approvals: u8,
+  pub approvers: Vec<AccountId>,
+  pub approved_by: Vec<AccountId>,
+  pub rejected_by: Vec<AccountId>,
+  pub status: String, // PENDING APPROVED REJECTED
+}
+
+// Offsets are counted from claimable_at
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PayoutSchedule {
+  Lump,
+  Linear { cliff: u64, duration: u64 },
+  Tranches { tranches: Vec<Tranche> },
+  Milestones { milestones: Vec<Milestone> },
+}
+
+fn days_to_epochs(days: u64) -> u64 {
+  let epochs = days.checked_mul(2);
+  assert!(epochs.is_some(), "Schedule offset is too large");
+  epochs.unwrap()
+}
+
+impl PayoutSchedule {
+  // Public methods take offsets in days, the same unit as cash_hold_time
+  pub fn in_epochs(self) -> Self {
+    match self {
+      PayoutSchedule::Lump => PayoutSchedule::Lump,
+      PayoutSchedule::Linear { cliff, duration } => PayoutSchedule::Linear {
+        cliff: days_to_epochs(cliff),
+        duration: days_to_epochs(duration),
+      },
+      PayoutSchedule::Tranches { tranches } => PayoutSchedule::Tranches {
+        tranches: tranches
+          .into_iter()
+          .map(|tranche| Tranche {
+            release_after: days_to_epochs(tranche.release_after),
+            share_bps: tranche.share_bps,
+          })
+          .collect(),
+      },
+      PayoutSchedule::Milestones { milestones } => PayoutSchedule::Milestones {
+        milestones: milestones
+          .into_iter()
+          .map(|milestone| Milestone {
+            deadline: days_to_epochs(milestone.deadline),
+            ..milestone
+          })
+          .collect(),
+      },
+    }
+  }
+
+  pub fn assert_valid(&self, claimable_at: u64) {
+    match self {
+      PayoutSchedule::Tranches { tranches } => {
+        let total_bps: u64 = tranches
+          .iter()
+          .map(|tranche| tranche.share_bps as u64)
+          .sum();
+        assert_eq!(total_bps, 10_000, "Tranche shares must add up to 10000 bps");
+      }
+      PayoutSchedule::Milestones { milestones } => {
+        let total_bps: u64 = milestones
+          .iter()
+          .map(|milestone| milestone.share_bps as u64)
+          .sum();
+        assert_eq!(
+          total_bps, 10_000,
+          "Milestone shares must add up to 10000 bps"
+        );
+        for milestone in milestones.iter() {
+          assert!(
+            env::is_valid_account_id(milestone.approver_id.as_bytes()),
+            "Invalid approver id"
+          );
+          assert!(
+            milestone.released_at.is_none() && milestone.reclaimed_at.is_none(),
+            "Milestones must start unreleased"
+          );
+          assert!(
+            claimable_at.checked_add(milestone.deadline).is_some(),
+            "Milestone deadline is too large"
+          );
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct TransferTransaction {
@@ -9,7 +123,147 @@ pub struct TransferTransaction {
   pub locked_balance: Balance,
   pub created_at: u64,
   pub claimable_at: u64,
-  pub status: String, // LOCK UNLOCK REFUND
+  pub refundable_until: u64,
+  pub schedule: PayoutSchedule,
+  pub claimed_amount: Balance,
+  pub claims: Vec<(u64, Balance, Balance)>, // (epoch, amount, fee) of the payouts, one per epoch
+  pub refunded_at: Option<u64>,
+  pub status: String,           // LOCK UNLOCK REFUND
+  pub transaction_type: String, // PAYMENT CONDITIONAL REFERRAL
+  pub approval: Option<TransactionApproval>,
+  pub organization_id: Option<String>, // paid for by the organization, sender_id is the acting member
+  pub platform_fee_bps: u16,           // rate in force when the payment was locked
+  pub held_fee: Balance,               // paid by the sender on top, not yet earned by the platform
+}
+
+impl TransferTransaction {
+  // Payments without an approval requirement are always approved
+  pub fn is_approved(&self) -> bool {
+    self
+      .approval
+      .as_ref()
+      .is_none_or(|approval| approval.status == "APPROVED")
+  }
+
+  pub fn vested_amount(&self, epoch: u64) -> Balance {
+    if epoch < self.claimable_at {
+      return 0;
+    }
+    let elapsed = epoch - self.claimable_at;
+    match &self.schedule {
+      PayoutSchedule::Lump => self.locked_balance,
+      PayoutSchedule::Linear { cliff, duration } => {
+        if elapsed < *cliff {
+          0
+        } else if elapsed >= *duration {
+          self.locked_balance
+        } else {
+          self.locked_balance * elapsed as u128 / *duration as u128
+        }
+      }
+      PayoutSchedule::Tranches { tranches } => {
+        let released_bps: u128 = tranches
+          .iter()
+          .filter(|tranche| elapsed >= tranche.release_after)
+          .map(|tranche| tranche.share_bps as u128)
+          .sum();
+        self.locked_balance * released_bps / 10_000
+      }
+      PayoutSchedule::Milestones { milestones } => milestones
+        .iter()
+        .enumerate()
+        .filter(|(_, milestone)| milestone.released_at.is_some_and(|at| at <= epoch))
+        .map(|(index, _)| self.milestone_amount(index))
+        .sum(),
+    }
+  }
+
+  // Shares are cut from the running total so the milestone amounts add up to the locked balance
+  pub fn milestone_amount(&self, index: usize) -> Balance {
+    match &self.schedule {
+      PayoutSchedule::Milestones { milestones } => {
+        let bps_until = |end: usize| -> u128 {
+          milestones[..end]
+            .iter()
+            .map(|milestone| milestone.share_bps as u128)
+            .sum()
+        };
+        self.locked_balance * bps_until(index + 1) / 10_000
+          - self.locked_balance * bps_until(index) / 10_000
+      }
+      _ => 0,
+    }
+  }
+
+  // (epoch, amount) of every milestone the sender took back
+  pub fn reclaims(&self) -> Vec<(u64, Balance)> {
+    match &self.schedule {
+      PayoutSchedule::Milestones { milestones } => milestones
+        .iter()
+        .enumerate()
+        .filter_map(|(index, milestone)| {
+          milestone
+            .reclaimed_at
+            .map(|at| (at, self.milestone_amount(index)))
+        })
+        .collect(),
+      _ => Vec::new(),
+    }
+  }
+
+  // Claims within one epoch share a record, so repeated claims don't keep growing the state.
+  // Returns the part of the sender's fee the claim releases to the platform
+  pub fn record_claim(&mut self, epoch: u64, amount: Balance) -> Balance {
+    self.claimed_amount += amount;
+    let fee = self.release_fee(amount);
+    match self.claims.last_mut() {
+      Some((claimed_at, claimed, claimed_fee)) if *claimed_at == epoch => {
+        *claimed += amount;
+        *claimed_fee += fee;
+      }
+      _ => self.claims.push((epoch, amount, fee)),
+    }
+    fee
+  }
+
+  // Takes back a claim whose payout never reached the receiver, its fee is held again
+  pub fn revert_claim(&mut self, epoch: u64, amount: Balance, fee: Balance) {
+    self.claimed_amount -= amount;
+    self.held_fee += fee;
+    if let Some(index) = self
+      .claims
+      .iter()
+      .rposition(|(claimed_at, _, _)| *claimed_at == epoch)
+    {
+      let (_, claimed, claimed_fee) = &mut self.claims[index];
+      *claimed -= amount;
+      *claimed_fee -= fee;
+      if *claimed == 0 {
+        self.claims.remove(index);
+      }
+    }
+  }
+
+  // Fee on a part of the payment that just left the outstanding balance, the last part takes
+  // whatever is held so rounding never strands any of it
+  pub fn release_fee(&mut self, amount: Balance) -> Balance {
+    let fee = if self.outstanding_amount() == 0 {
+      self.held_fee
+    } else {
+      std::cmp::min(
+        amount * self.platform_fee_bps as u128 / 10_000,
+        self.held_fee,
+      )
+    };
+    self.held_fee -= fee;
+    fee
+  }
+
+  // Still held for the receiver, neither paid out nor taken back
+  pub fn outstanding_amount(&self) -> Balance {
+    let reclaimed: Balance = self.reclaims().iter().map(|(_, amount)| amount).sum();
+    self.locked_balance - self.claimed_amount - reclaimed
+  }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -22,20 +276,34 @@ pub struct TransferTransactionJson {
   pub locked_balance: U128,
   pub created_at: u64,
   pub claimable_at: u64,
-  pub status: String, // LOCK UNLOCK REFUND
+  pub refundable_until: u64,
+  pub schedule: PayoutSchedule,
+  pub claimed_amount: U128,
+  pub status: String,           // LOCK UNLOCK REFUND
+  pub transaction_type: String, // PAYMENT CONDITIONAL REFERRAL
+  pub approval: Option<TransactionApproval>,
+  pub organization_id: Option<String>,
+  pub platform_fee_bps: u16,
 }
 
 impl TransferTransactionJson {
   pub fn from(transaction_id: String, transaction: TransferTransaction) -> Self {
     TransferTransactionJson {
-      transaction_id: transaction_id,
+      transaction_id,
       sender_id: transaction.sender_id,
       receiver_id: transaction.receiver_id,
       campaign_id: transaction.campaign_id,
       locked_balance: U128(transaction.locked_balance),
       created_at: transaction.created_at,
       claimable_at: transaction.claimable_at,
+      refundable_until: transaction.refundable_until,
+      schedule: transaction.schedule,
+      claimed_amount: U128(transaction.claimed_amount),
       status: transaction.status,
+      transaction_type: transaction.transaction_type,
+      approval: transaction.approval,
+      organization_id: transaction.organization_id,
+      platform_fee_bps: transaction.platform_fee_bps,
     }
   }
 }
@@ -1,5 +1,21 @@
 use crate::*;
 
+// Upper bound of the storage a single transfer transaction can use, in bytes
+pub const TRANSACTION_STORAGE_ESTIMATE: u64 = 1_000;
+
+// The contract names referral, subscription and invoice payments itself
+pub const RESERVED_TRANSACTION_ID_PREFIXES: [&str; 3] = ["referral-", "subscription-", "invoice-"];
+
+pub(crate) fn assert_user_transaction_id(transaction_id: &str) {
+  assert!(
+    !RESERVED_TRANSACTION_ID_PREFIXES
+      .iter()
+      .any(|prefix| transaction_id.starts_with(prefix)),
+    "Transaction ID {} is reserved",
+    transaction_id
+  );
+}
+
 pub(crate) fn assert_at_least_one_yocto() {
   assert!(
     env::attached_deposit() >= 1,
@@ -26,3 +42,11 @@ pub(crate) fn refund_deposit(amount: Balance, storaged_use: u64) {
     Promise::new(env::predecessor_account_id()).transfer(refund);
   }
 }
+
+pub(crate) fn now_in_seconds() -> u64 {
+  env::block_timestamp() / 1_000_000_000
+}
+
+pub(crate) fn today() -> u64 {
+  now_in_seconds() / 86_400
+}
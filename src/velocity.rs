@@ -0,0 +1,99 @@
+use crate::*;
+
+// Number of buckets a rolling window is split into
+pub const VELOCITY_BUCKETS: u64 = 10;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct VelocityLimit {
+  pub window: u64, // seconds
+  pub max_amount: Option<Balance>,
+  pub max_count: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VelocityLimitJson {
+  pub window: u64,
+  pub max_amount: Option<U128>,
+  pub max_count: Option<u64>,
+}
+
+impl VelocityLimit {
+  pub fn from(limit: VelocityLimitJson) -> Self {
+    assert!(limit.window > 0, "Velocity window must be positive");
+    VelocityLimit {
+      window: limit.window,
+      max_amount: limit.max_amount.map(|amount| amount.0),
+      max_count: limit.max_count,
+    }
+  }
+}
+
+impl VelocityLimitJson {
+  pub fn from(limit: VelocityLimit) -> Self {
+    VelocityLimitJson {
+      window: limit.window,
+      max_amount: limit.max_amount.map(U128),
+      max_count: limit.max_count,
+    }
+  }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct VelocityBucket {
+  pub started_at: u64,
+  pub amount: Balance,
+  pub count: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct VelocityTracker {
+  pub buckets: Vec<VelocityBucket>,
+}
+
+impl VelocityTracker {
+  // Drops the buckets that fell out of the rolling window
+  pub fn prune(&mut self, now: u64, window: u64) {
+    self
+      .buckets
+      .retain(|bucket| bucket.started_at + window > now);
+  }
+
+  pub fn totals(&self) -> (Balance, u64) {
+    self.buckets.iter().fold((0, 0), |(amount, count), bucket| {
+      (amount + bucket.amount, count + bucket.count)
+    })
+  }
+
+  // Returns the name of the exceeded limit if one more payment of `amount` does not fit
+  pub fn exceeded_limit(&self, limit: &VelocityLimit, amount: Balance) -> Option<&'static str> {
+    let (window_amount, window_count) = self.totals();
+    if let Some(max_amount) = limit.max_amount {
+      if window_amount + amount > max_amount {
+        return Some("amount");
+      }
+    }
+    if let Some(max_count) = limit.max_count {
+      if window_count + 1 > max_count {
+        return Some("count");
+      }
+    }
+    None
+  }
+
+  pub fn record(&mut self, now: u64, window: u64, amount: Balance) {
+    let bucket_size = std::cmp::max(window / VELOCITY_BUCKETS, 1);
+    let bucket_start = now - now % bucket_size;
+    match self.buckets.last_mut() {
+      Some(bucket) if bucket.started_at == bucket_start => {
+        bucket.amount += amount;
+        bucket.count += 1;
+      }
+      _ => self.buckets.push(VelocityBucket {
+        started_at: bucket_start,
+        amount,
+        count: 1,
+      }),
+    }
+  }
+}
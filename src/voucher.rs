@@ -0,0 +1,45 @@
+use crate::*;
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use std::convert::TryFrom;
+
+// Signed off-chain by the sender, the signature covers `voucher_message`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Voucher {
+  pub sender_id: AccountId,
+  pub receiver_id: AccountId,
+  pub amount: U128,
+  pub cash_hold_time: i64,
+  pub campaign_id: String,
+  pub transaction_id: String,
+  pub nonce: u64,
+  pub expires_at: u64, // epoch height
+}
+
+// Ties the signature to one deployment so a voucher cannot be replayed on another contract
+pub const VOUCHER_DOMAIN: &str = "hurdle-payment-voucher";
+
+// Domain, contract account id and the borsh serialization of the voucher
+pub fn voucher_message(contract_id: &str, voucher: &Voucher) -> Vec<u8> {
+  let mut message = format!("{}:{}:", VOUCHER_DOMAIN, contract_id).into_bytes();
+  message.extend(voucher.try_to_vec().unwrap());
+  message
+}
+
+pub(crate) fn verify_voucher_signature(
+  contract_id: &str,
+  public_key: &[u8],
+  voucher: &Voucher,
+  signature: &[u8],
+) -> bool {
+  let public_key = match PublicKey::from_bytes(public_key) {
+    Ok(public_key) => public_key,
+    Err(_) => return false,
+  };
+  let signature = match Signature::try_from(signature) {
+    Ok(signature) => signature,
+    Err(_) => return false,
+  };
+  let message = voucher_message(contract_id, voucher);
+  public_key.verify(&message, &signature).is_ok()
+}